@@ -1,9 +1,17 @@
 use git2::{BranchType, IndexAddOption, RemoteCallbacks, Repository, RepositoryInitOptions};
-use holochain_release_util::utils::push_tag;
-use holochain_release_util::{prepare_release, publish_release};
+use holochain_release_util::forge::{
+    Forge, ForgeKind, NewPullRequest, connect, repository_from_remote,
+};
+use holochain_release_util::utils::{GitRemote, push_tag};
+use holochain_release_util::{
+    ConfigOverrides, PublishOptions, PublishOutcome, VersionOptions, prepare_release,
+    publish_release,
+};
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
+const GITEA_URL: &str = "http://localhost:3000";
+
 fn git_token() -> String {
     static TOKEN: OnceLock<String> = OnceLock::new();
     TOKEN
@@ -61,7 +69,7 @@ impl TestHarness {
                 'w', 'x', 'y', 'z',
             ]
         );
-        let origin_url = format!("http://localhost:3000/gituser/{project_name}-{random_id}.git");
+        let origin_url = format!("{GITEA_URL}/gituser/{project_name}-{random_id}.git");
         println!(
             "Creating repository with origin: {}",
             temp_dir.path().display()
@@ -150,7 +158,7 @@ impl TestHarness {
     }
 
     pub fn tag(&self, tag: &str, message: &str) {
-        holochain_release_util::utils::tag(&self.repository, tag, message)
+        holochain_release_util::utils::tag(&self.repository, tag, message, None, false)
             .expect("Failed to create tag");
     }
 
@@ -172,8 +180,47 @@ impl TestHarness {
             .expect("Failed to push branch to remote");
     }
 
+    /// Fetch a branch from the remote, then check it out at the fetched commit.
+    pub fn pull_branch(&self, branch: &str) {
+        let mut remote = self
+            .repository
+            .find_remote("origin")
+            .expect("Failed to find remote 'origin'");
+
+        let mut fetch_opts = git2::FetchOptions::new();
+        fetch_opts.remote_callbacks(Self::make_cb());
+
+        remote
+            .fetch(
+                &[format!("refs/heads/{branch}:refs/remotes/origin/{branch}")],
+                Some(&mut fetch_opts),
+                None,
+            )
+            .expect("Failed to fetch branch from remote");
+
+        let commit = self
+            .repository
+            .find_reference(&format!("refs/remotes/origin/{branch}"))
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+
+        self.repository
+            .set_head(&format!("refs/heads/{branch}"))
+            .unwrap();
+        self.repository
+            .reset(commit.as_object(), git2::ResetType::Hard, None)
+            .expect("Failed to reset to the fetched branch");
+    }
+
     pub fn push_tag(&self, tag: &str) {
-        push_tag(&self.repository, &git_token(), tag).unwrap();
+        push_tag(
+            &self.repository,
+            &GitRemote::with_token(git_token()),
+            tag,
+            false,
+        )
+        .unwrap();
     }
 
     pub fn switch_branch(&self, branch: &str) {
@@ -213,6 +260,12 @@ impl TestHarness {
         );
     }
 
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.repository
+            .find_reference(&format!("refs/tags/{tag}"))
+            .is_ok()
+    }
+
     pub fn get_revision_for_tag(&self, tag: &str) -> String {
         holochain_release_util::utils::get_revision_for_tag(&self.repository, tag)
             .expect("Failed to get revision for tag")
@@ -356,7 +409,14 @@ edition = "2024"
                     .iter()
                     .map(|c| format!(
                         "{}_{} = {{ version = \"{}\", path = \"crates/{}\", registry = \"dev-registry\" }}",
-                        c.0.name, self.random_id, workspace_version, c.0.name
+                        c.0.name,
+                        self.random_id,
+                        if workspace_model.independent_versions {
+                            &c.0.version
+                        } else {
+                            &workspace_version
+                        },
+                        c.0.name
                     ))
                     .collect::<Vec<_>>()
                     .join("\n"),
@@ -370,7 +430,7 @@ edition = "2024"
                     r#"[package]
 name = "{}_{}"
 {}
-version.workspace = true
+{}
 edition.workspace = true
 {}
 {}
@@ -385,6 +445,11 @@ edition.workspace = true
                     } else {
                         String::new()
                     },
+                    if workspace_model.independent_versions {
+                        format!("version = \"{}\"", crate_model.version)
+                    } else {
+                        "version.workspace = true".to_string()
+                    },
                     if crate_model.repository.is_some() {
                         "repository.workspace = true".to_string()
                     } else {
@@ -502,8 +567,13 @@ edition.workspace = true
             .wait_with_output()
             .unwrap();
 
-        holochain_release_util::utils::get_version_from_cliff_output(&output.stdout)
-            .expect("Failed to get version from git-cliff output")
+        // With `--context`, git-cliff prints a list of releases, the first being the unreleased one.
+        let context = serde_json::from_slice::<serde_json::Value>(&output.stdout)
+            .expect("Unexpected output from git-cliff");
+        context[0]["version"]
+            .as_str()
+            .expect("Expected a version in git-cliff output")
+            .to_string()
     }
 
     pub fn set_version(&self, version: &str, push: bool) {
@@ -536,8 +606,7 @@ edition.workspace = true
     }
 
     pub fn get_current_version_from_workspace_cargo_toml(&self) -> String {
-        let content = self.read_file_content("Cargo.toml");
-        holochain_release_util::utils::get_current_version_from_cargo_toml(&content)
+        holochain_release_util::utils::get_current_version(self.temp_dir.path())
             .expect("Failed to get current version from Cargo.toml")
     }
 
@@ -622,6 +691,21 @@ edition.workspace = true
             .unwrap();
     }
 
+    /// Publish a single crate from the workspace, as if a publish had stopped after it.
+    pub fn publish_crate(&self, name: &str) {
+        let status = std::process::Command::new("cargo")
+            .current_dir(self.temp_dir.path())
+            .arg("publish")
+            .arg("--package")
+            .arg(format!("{}_{}", name, self.random_id))
+            .stdout(std::process::Stdio::inherit())
+            .stderr(std::process::Stdio::inherit())
+            .status()
+            .unwrap();
+
+        assert!(status.success(), "Failed to publish crate: {name}");
+    }
+
     pub fn run_prepare_release(
         &self,
         changelog_config: ChangelogConfig,
@@ -634,11 +718,190 @@ edition.workspace = true
             .unwrap()
             .to_string();
 
-        prepare_release(self.temp_dir.path(), cliff_config, force_version, false, false).unwrap();
+        prepare_release(
+            self.temp_dir.path(),
+            ConfigOverrides {
+                cliff_config: Some(cliff_config),
+                ..ConfigOverrides::default()
+            },
+            VersionOptions {
+                force_version,
+                ..VersionOptions::default()
+            },
+            false,
+            false,
+        )
+        .unwrap();
+    }
+
+    pub fn run_prepare_independent_release(&self, changelog_config: ChangelogConfig) {
+        let cliff_config = std::env::current_dir()
+            .unwrap()
+            .join(changelog_config.path())
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        prepare_release(
+            self.temp_dir.path(),
+            ConfigOverrides {
+                cliff_config: Some(cliff_config),
+                independent_versions: true,
+                ..ConfigOverrides::default()
+            },
+            VersionOptions::default(),
+            false,
+            false,
+        )
+        .unwrap();
     }
 
     pub fn run_publish_release(&self) {
-        publish_release(self.temp_dir.path(), git_token(), true, true).unwrap();
+        publish_release(
+            self.temp_dir.path(),
+            self.forge().as_ref(),
+            GitRemote::with_token(git_token()),
+            ConfigOverrides::default(),
+            PublishOptions {
+                danger_skip_releasable_changes_check: true,
+                danger_skip_create_release: true,
+                ..PublishOptions::default()
+            },
+        )
+        .unwrap();
+    }
+
+    pub fn run_publish_release_dry_run(&self) {
+        publish_release(
+            self.temp_dir.path(),
+            self.forge().as_ref(),
+            GitRemote::with_token(git_token()),
+            ConfigOverrides::default(),
+            PublishOptions {
+                danger_skip_releasable_changes_check: true,
+                danger_skip_create_release: true,
+                dry_run: true,
+                ..PublishOptions::default()
+            },
+        )
+        .unwrap();
+    }
+
+    pub fn run_publish_independent_release(&self) {
+        publish_release(
+            self.temp_dir.path(),
+            self.forge().as_ref(),
+            GitRemote::with_token(git_token()),
+            ConfigOverrides {
+                independent_versions: true,
+                ..ConfigOverrides::default()
+            },
+            PublishOptions {
+                danger_skip_releasable_changes_check: true,
+                danger_skip_create_release: true,
+                ..PublishOptions::default()
+            },
+        )
+        .unwrap();
+    }
+
+    /// Publish with the releasable change check and release creation enabled.
+    ///
+    /// The HEAD commit must have come from a pull request that was merged on Gitea. Returns
+    /// [`PublishOutcome::NotReleasable`] if the pull request does not have the release label.
+    pub fn run_publish_release_from_pull_request(&self) -> PublishOutcome {
+        publish_release(
+            self.temp_dir.path(),
+            self.forge().as_ref(),
+            GitRemote::with_token(git_token()),
+            ConfigOverrides::default(),
+            PublishOptions {
+                ..PublishOptions::default()
+            },
+        )
+        .unwrap()
+    }
+
+    /// Open a pull request on Gitea and return its number.
+    pub fn open_pull_request(&self, head: &str, base: &str, title: &str) -> u64 {
+        self.forge()
+            .open_pull_request(&NewPullRequest {
+                title: title.to_string(),
+                body: String::new(),
+                head: head.to_string(),
+                base: base.to_string(),
+            })
+            .expect("Failed to open pull request")
+            .number
+    }
+
+    /// Add a label to a pull request on Gitea, creating the label first.
+    pub fn add_label(&self, pull_request: u64, label: &str) {
+        let label = self
+            .gitea_request(reqwest::Method::POST, "labels")
+            .json(&serde_json::json!({ "name": label, "color": "#00aabb" }))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json::<serde_json::Value>())
+            .expect("Failed to create label");
+
+        self.gitea_request(
+            reqwest::Method::POST,
+            &format!("issues/{pull_request}/labels"),
+        )
+        .json(&serde_json::json!({ "labels": [label["id"]] }))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .expect("Failed to add label to pull request");
+    }
+
+    /// Merge a pull request on Gitea with a merge commit.
+    pub fn merge_pull_request(&self, pull_request: u64) {
+        self.gitea_request(
+            reqwest::Method::POST,
+            &format!("pulls/{pull_request}/merge"),
+        )
+        .json(&serde_json::json!({ "Do": "merge" }))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .expect("Failed to merge pull request");
+    }
+
+    /// Get the title of the Gitea release for a tag, if there is one.
+    pub fn get_release_title(&self, tag: &str) -> Option<String> {
+        let response = self
+            .gitea_request(reqwest::Method::GET, &format!("releases/tags/{tag}"))
+            .send()
+            .expect("Failed to get release");
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return None;
+        }
+
+        let release = response
+            .error_for_status()
+            .and_then(|response| response.json::<serde_json::Value>())
+            .expect("Failed to get release");
+        release["name"].as_str().map(ToString::to_string)
+    }
+
+    /// A forge client for the test repository on Gitea.
+    fn forge(&self) -> Box<dyn Forge> {
+        let repository = repository_from_remote(&self.repository).unwrap();
+        connect(ForgeKind::Gitea, Some(GITEA_URL), &repository, &git_token()).unwrap()
+    }
+
+    fn gitea_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+    ) -> reqwest::blocking::RequestBuilder {
+        let repository = repository_from_remote(&self.repository).unwrap();
+        reqwest::blocking::Client::new()
+            .request(
+                method,
+                format!("{GITEA_URL}/api/v1/repos/{repository}/{path}"),
+            )
+            .header("Authorization", format!("token {}", git_token()))
     }
 
     /// Retain the temporary directory and print its path.
@@ -740,6 +1003,7 @@ impl CrateModel {
 #[derive(Default)]
 pub struct CargoWorkspaceModel {
     crates: Vec<(CrateModel, Vec<String>)>,
+    independent_versions: bool,
 }
 
 impl CargoWorkspaceModel {
@@ -753,4 +1017,10 @@ impl CargoWorkspaceModel {
         ));
         self
     }
+
+    /// Give each crate its own version instead of inheriting the workspace version.
+    pub fn with_independent_versions(mut self) -> Self {
+        self.independent_versions = true;
+        self
+    }
 }
@@ -1,6 +1,7 @@
 //! Tests the `release-util` crate which combines the functionality that is being checked in the
 //! other integration tests here.
 
+use holochain_release_util::PublishOutcome;
 use integration::{CargoWorkspaceModel, ChangelogConfig, CrateModel, TestHarness};
 
 #[test]
@@ -50,6 +51,12 @@ fn release_a_library() {
         "Expected version 0.1.0 in Cargo.toml"
     );
 
+    //
+    // Check the release with a dry run, which must not create the tag
+    //
+    harness.run_publish_release_dry_run();
+    assert!(!harness.has_tag("v0.1.0"), "Dry run should not create a tag");
+
     //
     // Publish the release
     //
@@ -257,7 +264,12 @@ fn release_a_workspace() {
     );
 
     //
-    // Publish the release
+    // Publish only the library first, as if an earlier publish had failed part way through
+    //
+    harness.publish_crate("all-together-ws-lib");
+
+    //
+    // Publish the release, which must skip the library that is already in the registry
     //
     harness.run_publish_release();
 
@@ -297,6 +309,13 @@ fn release_a_workspace() {
         "Expected version 0.1.1 in Cargo.toml"
     );
 
+    //
+    // Check the release with a dry run. The binary depends on the new version of the library,
+    // which is not in the registry yet, so the crates must be checked together.
+    //
+    harness.run_publish_release_dry_run();
+    assert!(!harness.has_tag("v0.1.1"), "Dry run should not create a tag");
+
     //
     // Publish the new release
     //
@@ -414,3 +433,197 @@ pub fn subtract(a: i32, b: i32) -> i32 {
     //
     harness.run_publish_release();
 }
+
+#[test]
+fn release_a_workspace_with_independent_versions() {
+    let harness = TestHarness::new("all-together-independent");
+
+    //
+    // Initialize the repository
+    //
+    harness.add_standard_gitignore();
+    harness.add_private_registry_cargo_toml();
+    harness.write_file_content("README.md", "# all together independent");
+    harness.commit("README.md", "chore: Add README");
+    harness.push_branch("main");
+
+    //
+    // Add Rust source code
+    //
+    let library = CrateModel::new("all-together-ind-lib", "0.0.1")
+        .make_lib()
+        .with_description("All together independent library")
+        .with_repository(&harness.repository_url())
+        .with_license("Apache-2.0");
+
+    let binary = CrateModel::new("all-together-ind-bin", "0.0.1")
+        .with_description("All together independent binary")
+        .with_repository(&harness.repository_url())
+        .with_license("Apache-2.0");
+
+    harness.add_workspace(
+        CargoWorkspaceModel::default()
+            .with_independent_versions()
+            .add_crate(library, &[])
+            .add_crate(binary, &["all-together-ind-lib"]),
+    );
+    harness.verify_cargo_project(".");
+    harness.commit("*", "chore: Add workspace");
+    harness.push_branch("main");
+
+    //
+    // Prepare an initial release, every crate has changes so every crate gets a version
+    //
+    harness.run_prepare_independent_release(ChangelogConfig::Pre1Point0Cliff);
+    harness.commit("*", "chore: Prepare release");
+    harness.push_branch("main");
+
+    for crate_dir in ["all-together-ind-lib", "all-together-ind-bin"] {
+        let toml_content = harness.read_file_content(&format!("crates/{crate_dir}/Cargo.toml"));
+        assert!(
+            toml_content.contains("version = \"0.1.0\""),
+            "Expected version 0.1.0 in {crate_dir}"
+        );
+        harness.read_file_content(&format!("crates/{crate_dir}/CHANGELOG.md"));
+    }
+
+    //
+    // Publish the release
+    //
+    harness.run_publish_independent_release();
+    for crate_name in ["all-together-ind-lib", "all-together-ind-bin"] {
+        assert!(
+            harness.has_tag(&format!("{crate_name}-v0.1.0")),
+            "Expected {crate_name} to be tagged"
+        );
+    }
+    let binary_revision = harness.get_revision_for_tag("all-together-ind-bin-v0.1.0");
+
+    //
+    // Make a change to the library only
+    //
+    harness.write_file_content(
+        "crates/all-together-ind-lib/src/lib.rs",
+        "pub fn add(a: i32, b: i32) -> i32 { a + b }",
+    );
+    harness.verify_cargo_project(".");
+    harness.commit(
+        "crates/all-together-ind-lib/src/lib.rs",
+        "chore: Add add function",
+    );
+    harness.push_branch("main");
+
+    //
+    // Prepare a new release, only the library should move
+    //
+    harness.run_prepare_independent_release(ChangelogConfig::Pre1Point0Cliff);
+    harness.commit("*", "chore: Prepare next release");
+    harness.push_branch("main");
+
+    let toml_content = harness.read_file_content("crates/all-together-ind-lib/Cargo.toml");
+    assert!(
+        toml_content.contains("version = \"0.1.1\""),
+        "Expected version 0.1.1 for the library"
+    );
+    let toml_content = harness.read_file_content("crates/all-together-ind-bin/Cargo.toml");
+    assert!(
+        toml_content.contains("version = \"0.1.0\""),
+        "Expected version 0.1.0 for the binary"
+    );
+
+    //
+    // Publish the new release, the binary is already released so only the library is tagged.
+    // Publishing the binary again would fail, because its version is already in the registry.
+    //
+    harness.run_publish_independent_release();
+    assert!(
+        harness.has_tag("all-together-ind-lib-v0.1.1"),
+        "Expected the library to be tagged"
+    );
+    assert!(
+        !harness.has_tag("all-together-ind-bin-v0.1.1"),
+        "Expected no new tag for the binary"
+    );
+    assert_eq!(
+        harness.get_revision_for_tag("all-together-ind-bin-v0.1.0"),
+        binary_revision,
+        "Expected the binary's tag to stay where it was"
+    );
+}
+
+/// Release a library through a pull request that is labeled and merged on Gitea.
+///
+/// With this test, we get:
+/// - A merged pull request without the `hra-release` label is not released.
+/// - A merged pull request with the `hra-release` label is tagged, published and gets a release.
+#[test]
+fn release_through_a_labeled_pull_request() {
+    let harness = TestHarness::new("all-together-pr");
+
+    //
+    // Initialize the repository
+    //
+    harness.add_standard_gitignore();
+    harness.add_private_registry_cargo_toml();
+    harness.write_file_content("README.md", "# all together pull request");
+    harness.commit("README.md", "chore: Add README");
+    harness.push_branch("main");
+
+    //
+    // Add Rust source code through a pull request that is not labeled for release
+    //
+    let library = CrateModel::new("all-together-pr-lib", "0.0.1")
+        .make_lib()
+        .with_description("All together pull request library")
+        .with_repository(&harness.repository_url())
+        .with_license("Apache-2.0");
+
+    harness.switch_branch("add-library");
+    harness.add_crate(library);
+    harness.verify_cargo_project(".");
+    harness.commit("*", "chore: Add library");
+    harness.push_branch("add-library");
+
+    let pr = harness.open_pull_request("add-library", "main", "chore: Add library");
+    harness.merge_pull_request(pr);
+    harness.pull_branch("main");
+
+    //
+    // Publishing must stop because the pull request is not labeled
+    //
+    assert_eq!(
+        harness.run_publish_release_from_pull_request(),
+        PublishOutcome::NotReleasable
+    );
+    assert!(!harness.has_tag("v0.0.1"), "Expected no release");
+
+    //
+    // Prepare an initial release and open a labeled pull request for it
+    //
+    harness.switch_branch("release");
+    harness.run_prepare_release(ChangelogConfig::Pre1Point0Cliff, Some("v0.1.0".to_string()));
+    harness.commit("*", "chore: Prepare v0.1.0 release");
+    harness.push_branch("release");
+
+    let pr = harness.open_pull_request("release", "main", "chore: Prepare v0.1.0 release");
+    harness.add_label(pr, "hra-release");
+    harness.merge_pull_request(pr);
+    harness.pull_branch("main");
+
+    //
+    // Publish the release, which must be tagged and have a release on Gitea
+    //
+    assert_eq!(
+        harness.run_publish_release_from_pull_request(),
+        PublishOutcome::Published
+    );
+    assert!(harness.has_tag("v0.1.0"), "Expected the release to be tagged");
+
+    let title = harness
+        .get_release_title("v0.1.0")
+        .expect("Expected a release on Gitea");
+    assert!(
+        title.ends_with(" 0.1.0"),
+        "Unexpected release title: {title}"
+    );
+}
@@ -0,0 +1,216 @@
+//! Build the binaries of a crate and package them as release artifacts.
+//!
+//! Each binary is built for the host target in release mode, and packaged as
+//! `<bin>-<version>-<target>.tar.gz` next to a `.sha256` checksum file, in the format that
+//! `sha256sum --check` reads.
+
+use crate::error::{run_tool, tool_error, tool_failed};
+use crate::manifest::workspace_crates;
+use anyhow::Context;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Check that every crate with configured artifacts is a publishable crate in the workspace.
+pub(crate) fn check_artifact_crates(
+    dir: impl AsRef<Path>,
+    artifacts: &BTreeMap<String, Vec<String>>,
+) -> anyhow::Result<()> {
+    let crates = workspace_crates(dir)?;
+    for crate_name in artifacts.keys() {
+        if !crates.iter().any(|c| c.publish && &c.name == crate_name) {
+            anyhow::bail!(
+                "Artifacts are configured for '{crate_name}', which is not a publishable crate in the workspace"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the `bins` of `crate_name` and package them as release artifacts.
+///
+/// Returns the paths of the archives and their checksum files, which are written to the directory
+/// that cargo built the binaries into. With `dry_run`, nothing is built or written, and only the
+/// file names of the artifacts are returned.
+pub(crate) fn build_artifacts(
+    dir: impl AsRef<Path>,
+    crate_name: &str,
+    bins: &[String],
+    version: &str,
+    dry_run: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let target = host_target()?;
+
+    let mut artifacts = Vec::new();
+    for bin in bins {
+        let archive_name = format!("{bin}-{version}-{target}.tar.gz");
+        if dry_run {
+            println!("[dry-run] Would build {bin} from {crate_name} for {target}");
+            artifacts.push(PathBuf::from(&archive_name));
+            artifacts.push(PathBuf::from(format!("{archive_name}.sha256")));
+            continue;
+        }
+
+        println!("Building {bin} from {crate_name} for {target}");
+        let executable = build_binary(&dir, crate_name, bin)?;
+
+        let archive = executable.with_file_name(archive_name);
+        package_binary(&executable, &archive)?;
+        let checksum = write_checksum(&archive)?;
+        println!("Packaged {}", archive.display());
+
+        artifacts.push(archive);
+        artifacts.push(checksum);
+    }
+
+    Ok(artifacts)
+}
+
+/// Get the target triple of the host, from `rustc -vV`.
+fn host_target() -> anyhow::Result<String> {
+    let output = std::process::Command::new("rustc")
+        .arg("-vV")
+        .output()
+        .map_err(|e| tool_error("rustc", e))?;
+    if !output.status.success() {
+        return Err(tool_failed("rustc", &output).context("Failed to get the host target"));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
+        .context("No host target in the output of `rustc -vV`")
+}
+
+/// Build a binary in release mode and find the executable that cargo produced.
+fn build_binary(dir: impl AsRef<Path>, crate_name: &str, bin: &str) -> anyhow::Result<PathBuf> {
+    let output = run_tool(
+        "cargo",
+        std::process::Command::new("cargo")
+            .current_dir(&dir)
+            .arg("build")
+            .arg("--release")
+            .arg("--package")
+            .arg(crate_name)
+            .arg("--bin")
+            .arg(bin)
+            .arg("--message-format=json-render-diagnostics")
+            .stdout(std::process::Stdio::piped()),
+    )?;
+    if !output.status.success() {
+        return Err(tool_failed("cargo", &output).context(format!(
+            "Failed to build binary '{bin}' of crate: {crate_name}"
+        )));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|message| {
+            message["reason"] == "compiler-artifact" && message["target"]["name"] == bin
+        })
+        .find_map(|message| message["executable"].as_str().map(PathBuf::from))
+        .with_context(|| format!("Cargo did not report an executable for binary '{bin}'"))
+}
+
+/// Package an executable into a `.tar.gz` archive, with the executable at the top level.
+fn package_binary(executable: &Path, archive: &Path) -> anyhow::Result<()> {
+    let name = executable
+        .file_name()
+        .with_context(|| format!("Invalid executable path: {}", executable.display()))?;
+
+    let file =
+        File::create(archive).with_context(|| format!("Failed to create {}", archive.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder
+        .append_path_with_name(executable, name)
+        .with_context(|| format!("Failed to add {} to the archive", executable.display()))?;
+    builder
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .with_context(|| format!("Failed to write {}", archive.display()))?;
+
+    Ok(())
+}
+
+/// Write the SHA-256 checksum of a file to `<file>.sha256`, and return the path of that file.
+fn write_checksum(path: &Path) -> anyhow::Result<PathBuf> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Invalid file name: {}", path.display()))?;
+    let checksum_path = path.with_file_name(format!("{name}.sha256"));
+    std::fs::write(&checksum_path, format!("{:x}  {name}\n", hasher.finalize()))
+        .with_context(|| format!("Failed to write {}", checksum_path.display()))?;
+
+    Ok(checksum_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_only_names_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let artifacts =
+            build_artifacts(dir.path(), "missing", &["tool".to_string()], "0.1.0", true).unwrap();
+
+        let target = host_target().unwrap();
+        assert_eq!(
+            artifacts,
+            vec![
+                PathBuf::from(format!("tool-0.1.0-{target}.tar.gz")),
+                PathBuf::from(format!("tool-0.1.0-{target}.tar.gz.sha256")),
+            ]
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn package_and_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let executable = dir.path().join("tool");
+        std::fs::write(&executable, "#!/bin/sh\n").unwrap();
+
+        let archive = dir
+            .path()
+            .join("tool-0.1.0-x86_64-unknown-linux-gnu.tar.gz");
+        package_binary(&executable, &archive).unwrap();
+        let checksum = write_checksum(&archive).unwrap();
+
+        let mut entries =
+            tar::Archive::new(flate2::read::GzDecoder::new(File::open(&archive).unwrap()));
+        let names = entries
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![PathBuf::from("tool")]);
+
+        assert_eq!(
+            checksum,
+            dir.path()
+                .join("tool-0.1.0-x86_64-unknown-linux-gnu.tar.gz.sha256")
+        );
+        let expected = format!("{:x}", Sha256::digest(std::fs::read(&archive).unwrap()));
+        assert_eq!(
+            std::fs::read_to_string(&checksum).unwrap(),
+            format!("{expected}  tool-0.1.0-x86_64-unknown-linux-gnu.tar.gz\n")
+        );
+    }
+}
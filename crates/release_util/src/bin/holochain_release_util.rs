@@ -1,14 +1,37 @@
 use anyhow::Context;
-use clap::{Parser, Subcommand};
-use holochain_release_util::{prepare_release, publish_release};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use holochain_release_util::forge::{ForgeKind, connect, repository_from_remote};
+use holochain_release_util::utils::{GitCredentials, GitRemote};
+use holochain_release_util::{
+    ConfigOverrides, PreRelease, PublishOptions, ReleaseError, ReleaseType, VersionOptions,
+    check_workspace, doctor, plan_release, prepare_release, publish_release, verify_release_tag,
+};
 use std::path::PathBuf;
+use std::process::ExitCode;
+
+const EXIT_CODES: &str = "Exit codes:
+  0  Success
+  1  Other error
+  2  Invalid command line arguments
+  3  A required tool is not installed
+  4  A tool failed
+  5  Semver checks found changes that the release does not allow
+  6  Invalid release version
+  7  A release tag conflicts with an existing tag
+  8  Registry failure
+  9  Forge failure
+  10 The branch policy does not permit the release
+  11 Invalid release configuration
+  12 Tag signing or verification failed";
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_long_help = EXIT_CODES)]
 pub struct ReleaseUtilCli {
     /// The directory to run the command in.
     ///
-    /// Defaults to the current directory.
+    /// Defaults to the current directory. The release configuration is read from a
+    /// `release-util.toml` file, or from `[workspace.metadata.release-util]` in the `Cargo.toml`, in
+    /// this directory. Command line options take precedence over the configuration.
     #[arg(long, default_value = ".")]
     dir: PathBuf,
 
@@ -23,22 +46,42 @@ pub enum ReleaseUtilCommand {
     /// Picks or accepts the next semver version, generates a changelog, and sets the version in the
     /// Cargo project.
     Prepare {
-        /// The location of a `git-cliff` configuration file.
-        ///
-        /// This can either be a path to a file or a URL to a file.
-        #[arg(long)]
-        cliff_config: String,
+        #[command(flatten)]
+        config: ConfigArgs,
 
         /// Force the release version, rather than letting the tool pick the next semver version.
         ///
-        /// This should be used when switching to the next pre-release version or when switching
-        /// to a new release version from a pre-release version.
+        /// This should be used when switching to a new release version from a pre-release
+        /// version.
+        ///
+        /// The version must not already be tagged, and must come after the latest release on the
+        /// current branch.
         ///
         /// The code will treat an empty string the same as `None`, so it is safe to provide this
         /// argument without a value.
-        #[arg(long)]
+        #[arg(long, conflicts_with = "pre_release")]
         force_version: Option<String>,
 
+        /// Do not warn when the forced version skips past the next major, minor or patch version.
+        #[arg(long)]
+        allow_version_skip: bool,
+
+        /// Release the next pre-release on a channel, rather than a stable version.
+        ///
+        /// Continues the series from the latest tag on the branch, such as `0.5.0-dev.3` to
+        /// `0.5.0-dev.4`, or starts a new one from the next version, such as `0.5.0-rc.0`. Fails
+        /// if the pre-release would not come after the latest tag.
+        #[arg(long, value_enum)]
+        pre_release: Option<PreReleaseArg>,
+
+        /// Apply this kind of bump to the previous release, rather than picking it from the
+        /// commits.
+        ///
+        /// Useful for a breaking change that the commit messages do not flag. The previous release
+        /// is the latest release tag on the current branch.
+        #[arg(long, value_enum, conflicts_with_all = ["force_version", "pre_release"])]
+        bump: Option<BumpArg>,
+
         /// You are here because you created a crate with conflicting features.
         ///
         /// First, please read about the [feature system](https://doc.rust-lang.org/cargo/reference/features.html).
@@ -59,32 +102,404 @@ pub enum ReleaseUtilCommand {
         skip_semver_checks: bool,
     },
 
+    /// Print the plan for the next release, without changing anything.
+    ///
+    /// Shows the version that `prepare` would pick and why, the commits that would go in the
+    /// changelog, the crates that would be released and the semver checks that would run.
+    Plan {
+        #[command(flatten)]
+        config: ConfigArgs,
+
+        /// Force the release version, as for `prepare`.
+        #[arg(long, conflicts_with = "pre_release")]
+        force_version: Option<String>,
+
+        /// Allow the forced version to skip past the next version, as for `prepare`.
+        #[arg(long)]
+        allow_version_skip: bool,
+
+        /// Plan the next pre-release on a channel, as for `prepare`.
+        #[arg(long, value_enum)]
+        pre_release: Option<PreReleaseArg>,
+
+        /// Apply this kind of bump to the previous release, as for `prepare`.
+        #[arg(long, value_enum, conflicts_with_all = ["force_version", "pre_release"])]
+        bump: Option<BumpArg>,
+
+        /// Plan the release without semver checks, as for `prepare`.
+        #[arg(long)]
+        skip_semver_checks: bool,
+
+        /// The format to print the plan in.
+        ///
+        /// The JSON format is a list with one plan for the workspace, or one plan for each crate
+        /// to release with `--independent-versions`.
+        #[arg(long, value_enum, default_value_t = PlanFormat::Text)]
+        format: PlanFormat,
+    },
+
     /// Publish a release if one is found.
-    Publish,
+    Publish {
+        #[command(flatten)]
+        config: ConfigArgs,
+
+        /// Run every check and print the actions that would be taken, without taking them.
+        ///
+        /// Nothing is tagged, pushed, published or released.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Move a release tag that already exists on another commit, locally or on the remote.
+        ///
+        /// Without this, publishing fails when the release tag points elsewhere, because the release
+        /// may already be published. Even with it, the tag is not moved if any crate in the release
+        /// is already in its registry at the release version.
+        #[arg(long)]
+        allow_retag: bool,
+
+        /// The forge that hosts the repository, used to check pull requests and create releases.
+        #[arg(long, value_enum, default_value_t = ForgeArg::Github)]
+        forge: ForgeArg,
+
+        /// The API URL of the forge, for self-hosted instances.
+        ///
+        /// Defaults to the public API of GitHub. Required for Gitea, where it is the URL of the
+        /// instance, such as `http://localhost:3000`.
+        #[arg(long)]
+        forge_api_url: Option<String>,
+
+        /// The repository on the forge, as `owner/name`.
+        ///
+        /// Defaults to the `GITHUB_REPOSITORY` environment variable, or the URL of the `origin`
+        /// remote.
+        #[arg(long)]
+        repository: Option<String>,
+
+        /// The git remote to push release tags to.
+        #[arg(long, default_value = "origin")]
+        git_remote: String,
+
+        /// How to authenticate to the git remote when pushing release tags.
+        ///
+        /// A token is read from `--git-token-file`, or from the `GH_TOKEN` env var. An SSH key file
+        /// is given with `--git-ssh-key`, and its passphrase, if it has one, in the
+        /// `GIT_SSH_KEY_PASSPHRASE` env var.
+        #[arg(long, value_enum, default_value_t = GitCredentialsArg::Token)]
+        git_credentials: GitCredentialsArg,
+
+        /// The user name to authenticate to the git remote as.
+        ///
+        /// Defaults to the user name in the remote URL. For a token, it then defaults to the
+        /// `user.email` in the git configuration, and for SSH, to `git`.
+        #[arg(long)]
+        git_username: Option<String>,
+
+        /// A file that contains the token to push release tags with.
+        #[arg(long)]
+        git_token_file: Option<PathBuf>,
+
+        /// The SSH private key to push release tags with.
+        #[arg(long, required_if_eq("git_credentials", "ssh-key"))]
+        git_ssh_key: Option<PathBuf>,
+    },
+
+    /// Check that the tools, environment and configuration needed for a release are available.
+    ///
+    /// Checks the versions of `cargo` and `cargo-semver-checks`, the `GH_TOKEN` env var, the
+    /// forge repository, the git identity used to tag, and the registry credentials for each
+    /// publishable crate. Prints a table of the results with hints for fixing any failures.
+    Doctor,
+
+    /// Check that publishable crates inherit their fields from `[workspace.package]`.
+    ///
+    /// Covers `version`, `edition`, `license` and `repository`, where the workspace defines them.
+    /// The `version` is not checked when crates are versioned independently. The same check runs
+    /// when preparing a release.
+    CheckWorkspace {
+        /// Rewrite the fields as `<field>.workspace = true`, keeping the rest of the formatting.
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Verify the signature on a release tag.
+    ///
+    /// Uses the `signing` settings in the release configuration. SSH signatures are checked against
+    /// the `allowed-signers` file, and GPG signatures against the keyring, and against `key` if it
+    /// is set.
+    VerifyTag {
+        /// The tag to verify, such as `v0.1.0`.
+        tag: String,
+    },
 }
 
-fn main() -> anyhow::Result<()> {
-    println!("Starting release-util...");
+/// Options that override the release configuration, which are shared by the commands that release.
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// The location of a `git-cliff` configuration file.
+    ///
+    /// This can either be a path to a file or a URL to a file. Defaults to `cliff-config` in
+    /// the release configuration, or `cliff.toml`.
+    #[arg(long)]
+    cliff_config: Option<String>,
+
+    /// The label that marks a pull request as releasable, instead of `release-label`.
+    #[arg(long)]
+    release_label: Option<String>,
+
+    /// The prefix of release tags, instead of `tag-prefix`.
+    #[arg(long)]
+    tag_prefix: Option<String>,
+
+    /// Version each publishable crate in the workspace independently.
+    ///
+    /// Each crate gets its own `<crate>-vX.Y.Z` tags and a changelog in its own directory,
+    /// built from the commits that touch that crate. Crates must set an explicit version
+    /// rather than inheriting the workspace version. Publishing must use the same mode as the
+    /// release was prepared with.
+    ///
+    /// This can also be enabled with `independent-versions` in the release configuration.
+    #[arg(long)]
+    independent_versions: bool,
+
+    /// Also write a changelog for each crate in a workspace release, as `crate-changelogs` does.
+    #[arg(long)]
+    crate_changelogs: bool,
+
+    /// A pattern for the branches that may release, instead of the `branches` rules.
+    ///
+    /// Can be given more than once. Each is a regular expression that must match the whole branch
+    /// name.
+    #[arg(long = "allow-branch", value_name = "PATTERN")]
+    allow_branches: Vec<String>,
+}
+
+impl From<ConfigArgs> for ConfigOverrides {
+    fn from(value: ConfigArgs) -> Self {
+        ConfigOverrides {
+            cliff_config: value.cliff_config,
+            release_label: value.release_label,
+            tag_prefix: value.tag_prefix,
+            independent_versions: value.independent_versions,
+            crate_changelogs: value.crate_changelogs,
+            branches: value.allow_branches,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ForgeArg {
+    Github,
+    Gitea,
+}
+
+impl From<ForgeArg> for ForgeKind {
+    fn from(value: ForgeArg) -> Self {
+        match value {
+            ForgeArg::Github => ForgeKind::GitHub,
+            ForgeArg::Gitea => ForgeKind::Gitea,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GitCredentialsArg {
+    /// A token, sent as the password over HTTPS.
+    Token,
+    /// The keys that a running SSH agent holds.
+    SshAgent,
+    /// The SSH private key given with `--git-ssh-key`.
+    SshKey,
+    /// The credential helpers that are configured for git.
+    CredentialHelper,
+    /// No credentials, for remotes that do not ask for any, such as `file://` URLs.
+    None,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PreReleaseArg {
+    Dev,
+    Alpha,
+    Beta,
+    Rc,
+}
+
+impl From<PreReleaseArg> for PreRelease {
+    fn from(value: PreReleaseArg) -> Self {
+        match value {
+            PreReleaseArg::Dev => PreRelease::Dev,
+            PreReleaseArg::Alpha => PreRelease::Alpha,
+            PreReleaseArg::Beta => PreRelease::Beta,
+            PreReleaseArg::Rc => PreRelease::Rc,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BumpArg {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl From<BumpArg> for ReleaseType {
+    fn from(value: BumpArg) -> Self {
+        match value {
+            BumpArg::Major => ReleaseType::Major,
+            BumpArg::Minor => ReleaseType::Minor,
+            BumpArg::Patch => ReleaseType::Patch,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PlanFormat {
+    Text,
+    Json,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run() -> Result<(), ReleaseError> {
+    // Printed to stderr so that the output of `plan` can be consumed by other tools.
+    eprintln!("Starting release-util...");
     let cli = ReleaseUtilCli::parse();
 
     match cli.command {
         ReleaseUtilCommand::Prepare {
-            cliff_config,
+            config,
             force_version,
+            allow_version_skip,
+            pre_release,
+            bump,
             i_am_so_sorry_but_my_features_clash,
             skip_semver_checks,
         } => {
             prepare_release(
                 cli.dir,
-                cliff_config,
-                force_version,
+                config.into(),
+                VersionOptions {
+                    force_version,
+                    allow_version_skip,
+                    pre_release: pre_release.map(Into::into),
+                    bump: bump.map(Into::into),
+                },
                 skip_semver_checks,
                 i_am_so_sorry_but_my_features_clash,
             )?;
         }
-        ReleaseUtilCommand::Publish => {
-            let token = std::env::var("GH_TOKEN").context("Missing GH_TOKEN env var")?;
-            publish_release(cli.dir, token, false, false)?;
+        ReleaseUtilCommand::Plan {
+            config,
+            force_version,
+            allow_version_skip,
+            pre_release,
+            bump,
+            skip_semver_checks,
+            format,
+        } => {
+            let plans = plan_release(
+                cli.dir,
+                config.into(),
+                VersionOptions {
+                    force_version,
+                    allow_version_skip,
+                    pre_release: pre_release.map(Into::into),
+                    bump: bump.map(Into::into),
+                },
+                skip_semver_checks,
+            )?;
+
+            match format {
+                PlanFormat::Text => {
+                    for plan in &plans {
+                        print!("{plan}");
+                    }
+                }
+                PlanFormat::Json => {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&plans).context("Failed to serialize plan")?
+                    );
+                }
+            }
+        }
+        ReleaseUtilCommand::Publish {
+            config,
+            dry_run,
+            allow_retag,
+            forge,
+            forge_api_url,
+            repository,
+            git_remote,
+            git_credentials,
+            git_username,
+            git_token_file,
+            git_ssh_key,
+        } => {
+            let token = std::env::var("GH_TOKEN").map_err(|_| ReleaseError::Config {
+                message: "Missing GH_TOKEN env var".to_string(),
+            })?;
+            let repository = match repository.or_else(|| std::env::var("GITHUB_REPOSITORY").ok()) {
+                Some(repository) => repository,
+                None => repository_from_remote(
+                    &git2::Repository::open(&cli.dir).context("Failed to open git repository")?,
+                )?,
+            };
+            let forge = connect(forge.into(), forge_api_url.as_deref(), &repository, &token)?;
+
+            let credentials = match git_credentials {
+                GitCredentialsArg::Token => match git_token_file {
+                    Some(path) => GitCredentials::Token(
+                        std::fs::read_to_string(&path)
+                            .with_context(|| {
+                                format!("Failed to read git token file: {}", path.display())
+                            })?
+                            .trim()
+                            .to_string(),
+                    ),
+                    None => GitCredentials::Token(token),
+                },
+                GitCredentialsArg::SshAgent => GitCredentials::SshAgent,
+                GitCredentialsArg::SshKey => GitCredentials::SshKey {
+                    private_key: git_ssh_key.context("Missing --git-ssh-key")?,
+                    passphrase: std::env::var("GIT_SSH_KEY_PASSPHRASE").ok(),
+                },
+                GitCredentialsArg::CredentialHelper => GitCredentials::CredentialHelper,
+                GitCredentialsArg::None => GitCredentials::None,
+            };
+            let remote = GitRemote {
+                name: git_remote,
+                username: git_username,
+                credentials,
+            };
+
+            publish_release(
+                cli.dir,
+                forge.as_ref(),
+                remote,
+                config.into(),
+                PublishOptions {
+                    dry_run,
+                    allow_retag,
+                    ..PublishOptions::default()
+                },
+            )?;
+        }
+        ReleaseUtilCommand::Doctor => {
+            doctor(cli.dir)?;
+        }
+        ReleaseUtilCommand::CheckWorkspace { fix } => {
+            check_workspace(cli.dir, fix)?;
+        }
+        ReleaseUtilCommand::VerifyTag { tag } => {
+            verify_release_tag(cli.dir, &tag)?;
         }
     }
 
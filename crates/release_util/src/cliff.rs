@@ -0,0 +1,488 @@
+//! In-process changelog generation using the `git-cliff-core` library.
+//!
+//! The repository history is walked once per run to build a [`CliffContext`]. That context is then
+//! used to pick the next version, render the changelog and find the previous release, so that all
+//! three agree with each other and with the pinned version of `git-cliff-core`.
+
+use crate::manifest::WorkspaceCrate;
+use anyhow::Context;
+use git_cliff_core::changelog::Changelog;
+use git_cliff_core::commit::{Commit, Range};
+use git_cliff_core::config::Config;
+use git_cliff_core::release::Release;
+use git_cliff_core::repo::Repository;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Matches the version part of a release tag, including pre-release versions.
+const VERSION_PATTERN: &str = "\\d+.\\d+.\\d+";
+
+/// The parsed `git-cliff` context for the unreleased changes on the current branch.
+pub(crate) struct CliffContext {
+    changelog: Changelog<'static>,
+    changelog_path: PathBuf,
+    next_version_tag: Option<String>,
+    released_version_tag: Option<String>,
+}
+
+impl CliffContext {
+    /// Load the `git-cliff` configuration and process the unreleased changes in the repository.
+    ///
+    /// The `cliff_config` can be either a path, relative to `dir`, or a URL to a configuration
+    /// file. If a `force_tag` is provided, it is used as the version for the unreleased changes
+    /// instead of letting `git-cliff` bump the version.
+    ///
+    /// Only release tags that are the `tag_prefix` followed by a version are considered. With
+    /// `stable_only`, pre-release tags are ignored too, so the next version is bumped from the
+    /// previous stable release.
+    ///
+    /// When a `crate_scope` is provided, only the commits that touch that crate's directory and
+    /// the release tags for that crate are considered. The changelog is then written to the
+    /// crate's directory.
+    pub(crate) fn load(
+        dir: impl AsRef<Path>,
+        cliff_config: &str,
+        tag_prefix: &str,
+        force_tag: &Option<String>,
+        stable_only: bool,
+        crate_scope: Option<&WorkspaceCrate>,
+    ) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut config = load_config(dir, cliff_config)?;
+
+        configure(&mut config, tag_prefix, force_tag, stable_only, crate_scope)?;
+
+        let changelog_path = match crate_scope {
+            Some(scope) => dir.join(&scope.path).join("CHANGELOG.md"),
+            None => dir.join("CHANGELOG.md"),
+        };
+
+        // The header is stripped from an existing changelog when prepending, but the footer would
+        // be repeated, so it must be left out of the template.
+        if changelog_path.exists() {
+            config.changelog.footer = None;
+        }
+
+        let repository = Repository::discover(
+            dir.canonicalize()
+                .context("Failed to resolve repository directory")?,
+        )
+        .context("Failed to open git repository for git-cliff")?;
+
+        detect_remote(&repository, &mut config);
+
+        let mut tags = repository
+            .tags(&config.git.tag_pattern, config.git.topo_order, true)
+            .context("Failed to list release tags")?;
+        tags.retain(|_, tag| {
+            let name = &tag.name;
+            let skip = config
+                .git
+                .skip_tags
+                .as_ref()
+                .is_some_and(|r| r.is_match(name));
+            let count = config
+                .git
+                .count_tags
+                .as_ref()
+                .is_none_or(|r| r.is_match(name));
+            let ignore = config
+                .git
+                .ignore_tags
+                .as_ref()
+                .is_some_and(|r| !r.as_str().trim().is_empty() && r.is_match(name));
+
+            skip || (count && !ignore)
+        });
+
+        let released_version_tag = tags.last().map(|(_, tag)| tag.name.clone());
+        let commit_range = released_version_tag
+            .as_ref()
+            .map(|tag| format!("{tag}..HEAD"));
+
+        let include_paths =
+            (!config.git.include_paths.is_empty()).then(|| config.git.include_paths.clone());
+        let exclude_paths =
+            (!config.git.exclude_paths.is_empty()).then(|| config.git.exclude_paths.clone());
+        let mut commits = repository
+            .commits(
+                commit_range.as_deref(),
+                include_paths,
+                exclude_paths,
+                config.git.topo_order_commits,
+            )
+            .context("Failed to read commits")?;
+        if let Some(limit) = config.git.limit_commits {
+            commits.truncate(limit);
+        }
+
+        if let Some(tag) = force_tag
+            && let Some(commit_id) = commits.first().map(|c| c.id().to_string())
+        {
+            match tags.get(&commit_id) {
+                Some(existing) => {
+                    eprintln!(
+                        "WARNING: There is already a tag ({}) for {}",
+                        existing.name, commit_id
+                    );
+                }
+                None => {
+                    tags.insert(commit_id, repository.resolve_tag(tag));
+                }
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System time is before the UNIX epoch")?
+            .as_secs() as i64;
+        let repository_path = repository
+            .root_path()
+            .context("Failed to get repository root")?
+            .to_string_lossy()
+            .into_owned();
+
+        let mut releases = vec![Release::default()];
+        let mut previous_release = Release::default();
+        for git_commit in commits.iter().rev() {
+            let release = releases.last_mut().expect("Always at least one release");
+            let mut commit = Commit::from(git_commit);
+            commit.statistics = repository
+                .commit_statistics(git_commit)
+                .context("Failed to get commit statistics")?;
+            release.commit_id = Some(commit.id.clone());
+            release.commits.push(commit);
+            release.repository = Some(repository_path.clone());
+
+            if let Some(tag) = tags.get(release.commit_id.as_ref().expect("Just set")) {
+                release.version = Some(tag.name.clone());
+                release.message.clone_from(&tag.message);
+                release.timestamp = if force_tag.as_deref() == Some(tag.name.as_str()) {
+                    Some(now)
+                } else {
+                    Some(git_commit.time().seconds())
+                };
+                previous_release.previous = None;
+                release.previous = Some(Box::new(previous_release));
+                previous_release = release.clone();
+                releases.push(Release::default());
+            }
+        }
+
+        if releases.len() > 1 {
+            previous_release.previous = None;
+            releases.last_mut().expect("Checked length").previous =
+                Some(Box::new(previous_release));
+        }
+
+        // Link the unreleased changes to the previous release when no tag was found in the range.
+        let previous_tag = tags
+            .iter()
+            .find(|(_, tag)| Some(&tag.name) == released_version_tag.as_ref());
+        if let Some((commit_id, tag)) = previous_tag
+            && releases[0]
+                .previous
+                .as_ref()
+                .and_then(|p| p.version.as_ref())
+                .is_none()
+        {
+            releases[0].previous = Some(Box::new(Release {
+                commit_id: Some(commit_id.clone()),
+                version: Some(tag.name.clone()),
+                timestamp: Some(
+                    repository
+                        .find_commit(commit_id)
+                        .map(|c| c.time().seconds())
+                        .unwrap_or_default(),
+                ),
+                ..Default::default()
+            }));
+        }
+
+        let newest_first = config.git.sort_commits.eq_ignore_ascii_case("newest");
+        for release in &mut releases {
+            if newest_first {
+                release.commits.reverse();
+            }
+            if let (Some(first), Some(last)) = (release.commits.first(), release.commits.last()) {
+                release.commit_range = Some(if newest_first {
+                    Range::new(last, first)
+                } else {
+                    Range::new(first, last)
+                });
+            }
+        }
+
+        let mut changelog = Changelog::new(releases, config, commit_range.as_deref())
+            .context("Failed to process commits for the changelog")?;
+
+        // There is no next version if there are no unreleased changes.
+        let next_version_tag = match changelog.bump_version().context("Failed to bump version")? {
+            Some(next_version) => Some(next_version),
+            None => changelog
+                .releases
+                .first()
+                .and_then(|release| release.version.clone()),
+        };
+
+        if let Some(next_version_tag) = &next_version_tag
+            && let Some(tag_pattern) = &changelog.config.git.tag_pattern
+            && !tag_pattern.is_match(next_version_tag)
+        {
+            anyhow::bail!(
+                "Next version ({next_version_tag}) does not match the tag pattern: {tag_pattern}"
+            );
+        }
+
+        Ok(Self {
+            changelog,
+            changelog_path,
+            next_version_tag,
+            released_version_tag,
+        })
+    }
+
+    /// The tag for the next release, either forced or picked by `git-cliff`.
+    ///
+    /// This is `None` if there are no unreleased changes.
+    pub(crate) fn next_version_tag(&self) -> Option<&str> {
+        self.next_version_tag.as_deref()
+    }
+
+    /// The tag of the latest release reachable from the current branch, if there is one.
+    pub(crate) fn released_version_tag(&self) -> Option<&str> {
+        self.released_version_tag.as_deref()
+    }
+
+    /// The unreleased commits, after they have been parsed and grouped by `git-cliff`.
+    pub(crate) fn unreleased_commits(&self) -> &[Commit<'static>] {
+        self.changelog
+            .releases
+            .first()
+            .map(|release| release.commits.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The path of the `CHANGELOG.md` that the changes belong in.
+    ///
+    /// This is in the repository root, or in the crate directory when the context is scoped to a
+    /// crate.
+    pub(crate) fn changelog_path(&self) -> &Path {
+        &self.changelog_path
+    }
+
+    /// Render the changelog with the unreleased changes, without writing it.
+    ///
+    /// If the changelog already exists, the new section is prepended to its current content.
+    pub(crate) fn render_changelog(&self) -> anyhow::Result<String> {
+        let mut out = Vec::new();
+
+        if self.changelog_path.exists() {
+            let existing = std::fs::read_to_string(&self.changelog_path)
+                .context("Failed to read existing changelog")?;
+            self.changelog
+                .prepend(existing, &mut out)
+                .context("Failed to prepend to changelog")?;
+        } else {
+            self.changelog
+                .generate(&mut out)
+                .context("Failed to render changelog")?;
+        }
+
+        String::from_utf8(out).context("Changelog is not valid UTF-8")
+    }
+}
+
+/// Load the `git-cliff` configuration from a URL or a file path.
+fn load_config(dir: &Path, cliff_config: &str) -> anyhow::Result<Config> {
+    if url::Url::parse(cliff_config).is_ok() {
+        eprintln!("Fetching git-cliff configuration from: {cliff_config}");
+        reqwest::blocking::get(cliff_config)
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .context("Failed to fetch git-cliff configuration")?
+            .parse()
+            .context("Invalid git-cliff configuration")
+    } else {
+        Config::load(&dir.join(cliff_config)).context("Failed to load git-cliff configuration")
+    }
+}
+
+/// Apply the settings that this tool relies on, over the top of the loaded configuration.
+///
+/// Pre-release tags are also ignored when the next version is being forced to a stable version.
+/// That allows a pre-release series to be followed by a stable release that accounts for all the
+/// changes since the previous stable release.
+fn configure(
+    config: &mut Config,
+    tag_prefix: &str,
+    force_tag: &Option<String>,
+    stable_only: bool,
+    crate_scope: Option<&WorkspaceCrate>,
+) -> anyhow::Result<()> {
+    let stable_only = stable_only
+        || force_tag.as_ref().is_some_and(|tag| {
+            tag.strip_prefix(tag_prefix)
+                .and_then(|version| semver::Version::parse(version).ok())
+                .is_some_and(|version| version.pre.is_empty())
+        });
+    let tag_pattern = format!(
+        "^{}{VERSION_PATTERN}{}",
+        regex::escape(tag_prefix),
+        if stable_only { "$" } else { "" }
+    );
+    config.git.tag_pattern = Some(regex::Regex::new(&tag_pattern).context("Invalid tag pattern")?);
+    config.git.use_branch_tags = true;
+    config.git.skip_tags = config
+        .git
+        .skip_tags
+        .take()
+        .filter(|r| !r.as_str().is_empty());
+
+    if let Some(tag) = force_tag {
+        config.bump.initial_tag = Some(tag.clone());
+    } else {
+        // Keep the initial version from the configuration, but give it the release tag prefix.
+        let initial_tag = config.bump.get_initial_tag();
+        config.bump.initial_tag = Some(format!(
+            "{tag_prefix}{}",
+            initial_tag.trim_start_matches('v')
+        ));
+    }
+
+    if let Some(scope) = crate_scope {
+        let pattern = scope.path.join("**").join("*");
+        config.git.include_paths.push(
+            glob::Pattern::new(&pattern.to_string_lossy()).context("Invalid crate path pattern")?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Use the upstream remote of the current branch for remote metadata, if none is configured.
+fn detect_remote(repository: &Repository, config: &mut Config) {
+    if !config.remote.is_any_set()
+        && let Ok(remote) = repository.upstream_remote()
+    {
+        config.remote.github.owner = remote.owner;
+        config.remote.github.repo = remote.repo;
+        config.remote.github.is_custom = remote.is_custom;
+    }
+
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        config.remote.github.token = Some(secrecy::SecretString::new(token));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIFF_CONFIG: &str = r#"
+[changelog]
+body = """
+{% if version %}## {{ version }}{% else %}## Unreleased{% endif %}
+{% for commit in commits %}
+- {{ commit.message | split(pat="\n") | first }}
+{%- endfor %}
+"""
+trim = true
+
+[git]
+conventional_commits = true
+filter_unconventional = false
+
+[bump]
+initial_tag = "v0.1.0"
+"#;
+
+    fn commit(repository: &git2::Repository, message: &str) {
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree_id = repository.index().unwrap().write_tree().unwrap();
+        let tree = repository.find_tree(tree_id).unwrap();
+        let parents = repository
+            .head()
+            .ok()
+            .map(|head| vec![head.peel_to_commit().unwrap()])
+            .unwrap_or_default();
+        let parents = parents.iter().collect::<Vec<_>>();
+        repository
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+    }
+
+    fn setup() -> (tempfile::TempDir, git2::Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = git2::Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("cliff.toml"), CLIFF_CONFIG).unwrap();
+        commit(&repository, "chore: init");
+        (dir, repository)
+    }
+
+    #[test]
+    fn initial_release_uses_initial_tag() {
+        let (dir, _repository) = setup();
+
+        let context =
+            CliffContext::load(dir.path(), "cliff.toml", "v", &None, false, None).unwrap();
+
+        assert_eq!(context.next_version_tag(), Some("v0.1.0"));
+        assert_eq!(context.released_version_tag(), None);
+    }
+
+    #[test]
+    fn bump_from_previous_release() {
+        let (dir, repository) = setup();
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        repository
+            .tag_lightweight("v0.1.0", head.as_object(), false)
+            .unwrap();
+        commit(&repository, "fix: a bug");
+
+        let context =
+            CliffContext::load(dir.path(), "cliff.toml", "v", &None, false, None).unwrap();
+
+        assert_eq!(context.next_version_tag(), Some("v0.1.1"));
+        assert_eq!(context.released_version_tag(), Some("v0.1.0"));
+
+        let changelog = context.render_changelog().unwrap();
+        assert!(changelog.contains("## v0.1.1"), "{changelog}");
+        assert!(changelog.contains("- a bug"), "{changelog}");
+    }
+
+    #[test]
+    fn forced_stable_version_ignores_pre_release_tags() {
+        let (dir, repository) = setup();
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        repository
+            .tag_lightweight("v0.1.0", head.as_object(), false)
+            .unwrap();
+        commit(&repository, "feat: a feature");
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        repository
+            .tag_lightweight("v0.2.0-dev.0", head.as_object(), false)
+            .unwrap();
+        commit(&repository, "docs: some docs");
+
+        let context = CliffContext::load(
+            dir.path(),
+            "cliff.toml",
+            "v",
+            &Some("v0.2.0".to_string()),
+            false,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(context.next_version_tag(), Some("v0.2.0"));
+        assert_eq!(context.released_version_tag(), Some("v0.1.0"));
+    }
+}
@@ -0,0 +1,489 @@
+//! Per-repository configuration for the release process.
+//!
+//! The configuration is read from a `release-util.toml` file in the root of the repository, or
+//! from `[workspace.metadata.release-util]` in the root `Cargo.toml`. For a single crate, that is
+//! `[package.metadata.release-util]`. Every setting has a default that matches the behaviour
+//! without any configuration, and command line flags take precedence over the configuration.
+
+use crate::RELEASE_LABEL;
+use crate::error::{ReleaseError, into_release_error};
+use crate::signing::SigningConfig;
+use crate::utils::current_branch;
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The name of the configuration file, in the root of the repository.
+pub const CONFIG_FILE: &str = "release-util.toml";
+
+/// The release settings for a repository.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ReleaseConfig {
+    /// The location of the `git-cliff` configuration file.
+    ///
+    /// This can either be a path, relative to the repository, or a URL.
+    pub cliff_config: String,
+    /// The label that marks a pull request as releasable.
+    pub release_label: String,
+    /// The prefix of release tags, which is followed by the version.
+    ///
+    /// Crates that are versioned independently are tagged with `<crate>-<prefix><version>`.
+    pub tag_prefix: String,
+    /// Whether each publishable crate in the workspace is versioned independently.
+    pub independent_versions: bool,
+    /// Whether to also write a changelog in each publishable crate's directory, with only the
+    /// commits that touch that crate.
+    ///
+    /// The root changelog is still written for the whole workspace. Crates that are versioned
+    /// independently always get their own changelog.
+    pub crate_changelogs: bool,
+    /// The binaries to attach to the forge release, by the name of the publishable crate that
+    /// provides them.
+    ///
+    /// Each binary is built for the host target in release mode, and attached as a `.tar.gz`
+    /// archive with a SHA-256 checksum file.
+    pub artifacts: BTreeMap<String, Vec<String>>,
+    /// How to sign release tags. Tags are not signed when this is not set.
+    pub signing: Option<SigningConfig>,
+    /// The branches that releases may be prepared and published from.
+    ///
+    /// The first rule that matches the current branch applies. Defaults to the branches that start
+    /// with `main` or `release`. An empty list lets releases be made from any branch.
+    pub branches: Vec<BranchRule>,
+}
+
+/// Settings from the command line, which take precedence over the [`ReleaseConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigOverrides {
+    /// Replaces `cliff-config`, unless it is empty.
+    pub cliff_config: Option<String>,
+    /// Replaces `release-label`.
+    pub release_label: Option<String>,
+    /// Replaces `tag-prefix`.
+    pub tag_prefix: Option<String>,
+    /// Enables `independent-versions`.
+    pub independent_versions: bool,
+    /// Enables `crate-changelogs`.
+    pub crate_changelogs: bool,
+    /// Replaces the `branches` rules with these patterns, when any are given.
+    pub branches: Vec<String>,
+}
+
+/// A branch, or set of branches, that releases may be made from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BranchRule {
+    /// A regular expression that must match the whole branch name.
+    ///
+    /// Named groups called `major` and `minor` restrict the versions that may be released from
+    /// the branch. For example, `release-(?<major>\d+)\.(?<minor>\d+)` only permits `0.2.x`
+    /// versions from a `release-0.2` branch.
+    pub pattern: String,
+    /// A semver requirement, such as `<1.0.0`, that released versions must meet.
+    ///
+    /// Pre-release identifiers are ignored when matching, so `0.3.0-dev.1` meets `>=0.3.0`.
+    #[serde(default)]
+    pub versions: Option<String>,
+}
+
+impl BranchRule {
+    /// A rule that permits any version from the branches that match the `pattern`.
+    pub fn new(pattern: impl Into<String>) -> Self {
+        BranchRule {
+            pattern: pattern.into(),
+            versions: None,
+        }
+    }
+
+    fn regex(&self) -> anyhow::Result<regex::Regex> {
+        regex::Regex::new(&format!("^(?:{})$", self.pattern))
+            .with_context(|| format!("Invalid branch pattern: {}", self.pattern))
+    }
+
+    fn version_req(&self) -> anyhow::Result<Option<semver::VersionReq>> {
+        self.versions
+            .as_deref()
+            .map(|versions| {
+                semver::VersionReq::parse(versions)
+                    .with_context(|| format!("Invalid version requirement: {versions}"))
+            })
+            .transpose()
+    }
+}
+
+impl Default for ReleaseConfig {
+    fn default() -> Self {
+        ReleaseConfig {
+            cliff_config: "cliff.toml".to_string(),
+            release_label: RELEASE_LABEL.to_string(),
+            tag_prefix: "v".to_string(),
+            independent_versions: false,
+            crate_changelogs: false,
+            artifacts: BTreeMap::new(),
+            signing: None,
+            branches: vec![BranchRule::new("main.*"), BranchRule::new("release.*")],
+        }
+    }
+}
+
+impl ReleaseConfig {
+    /// Load the configuration for the repository in `dir`.
+    ///
+    /// Uses the defaults if there is no configuration. Fails if the configuration is invalid, or if
+    /// it is given both in a file and in the `Cargo.toml` metadata.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, ReleaseError> {
+        Self::read(dir.as_ref()).map_err(config_error)
+    }
+
+    fn read(dir: &Path) -> anyhow::Result<Self> {
+        let config_path = dir.join(CONFIG_FILE);
+        let from_file =
+            if config_path.is_file() {
+                let content = std::fs::read_to_string(&config_path)
+                    .with_context(|| format!("Failed to read {}", config_path.display()))?;
+                Some(toml::from_str::<ReleaseConfig>(&content).with_context(|| {
+                    format!("Invalid configuration in {}", config_path.display())
+                })?)
+            } else {
+                None
+            };
+
+        let manifest_path = dir.join("Cargo.toml");
+        let from_metadata = if manifest_path.is_file() {
+            let content = std::fs::read_to_string(&manifest_path)
+                .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+            let manifest = toml::from_str::<toml::Table>(&content)
+                .with_context(|| format!("Invalid TOML in {}", manifest_path.display()))?;
+            ["workspace", "package"]
+                .iter()
+                .find_map(|table| {
+                    manifest
+                        .get(*table)?
+                        .get("metadata")?
+                        .get("release-util")
+                        .cloned()
+                })
+                .map(|value| {
+                    value.try_into::<ReleaseConfig>().with_context(|| {
+                        format!(
+                            "Invalid `release-util` metadata in {}",
+                            manifest_path.display()
+                        )
+                    })
+                })
+                .transpose()?
+        } else {
+            None
+        };
+
+        let config = match (from_file, from_metadata) {
+            (Some(_), Some(_)) => anyhow::bail!(
+                "Release configuration is in both {CONFIG_FILE} and the `release-util` metadata in Cargo.toml, remove one of them"
+            ),
+            (Some(config), None) | (None, Some(config)) => config,
+            (None, None) => ReleaseConfig::default(),
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Apply the values from command line flags, which take precedence over the configuration.
+    ///
+    /// Fails if the result is not a valid configuration.
+    pub(crate) fn with_overrides(mut self, overrides: ConfigOverrides) -> anyhow::Result<Self> {
+        let ConfigOverrides {
+            cliff_config,
+            release_label,
+            tag_prefix,
+            independent_versions,
+            crate_changelogs,
+            branches,
+        } = overrides;
+
+        if let Some(cliff_config) = cliff_config.filter(|c| !c.is_empty()) {
+            self.cliff_config = cliff_config;
+        }
+        if let Some(release_label) = release_label {
+            self.release_label = release_label;
+        }
+        if let Some(tag_prefix) = tag_prefix {
+            self.tag_prefix = tag_prefix;
+        }
+        self.independent_versions |= independent_versions;
+        self.crate_changelogs |= crate_changelogs;
+        if !branches.is_empty() {
+            self.branches = branches.into_iter().map(BranchRule::new).collect();
+        }
+
+        self.validate()
+            .context("Invalid command line overrides")
+            .map_err(config_error)?;
+
+        Ok(self)
+    }
+
+    /// The prefix of the release tags for the workspace, or for a crate that is versioned
+    /// independently.
+    pub(crate) fn release_tag_prefix(&self, crate_name: Option<&str>) -> String {
+        match crate_name {
+            Some(crate_name) => format!("{crate_name}-{}", self.tag_prefix),
+            None => self.tag_prefix.clone(),
+        }
+    }
+
+    /// Check that the branch policy permits releasing `version` from the current branch.
+    pub(crate) fn check_branch_policy(
+        &self,
+        repository: &git2::Repository,
+        version: &semver::Version,
+    ) -> anyhow::Result<()> {
+        if self.branches.is_empty() {
+            return Ok(());
+        }
+
+        let branch = current_branch(repository)?;
+        self.check_branch(&branch, version).map_err(|e| {
+            into_release_error(e, |message| ReleaseError::BranchPolicy {
+                branch: branch.clone(),
+                message,
+            })
+            .into()
+        })
+    }
+
+    fn check_branch(&self, branch: &str, version: &semver::Version) -> anyhow::Result<()> {
+        for rule in &self.branches {
+            let Some(captures) = rule.regex()?.captures(branch) else {
+                continue;
+            };
+
+            for (part, actual) in [("major", version.major), ("minor", version.minor)] {
+                let Some(expected) = captures.name(part) else {
+                    continue;
+                };
+                let expected = expected.as_str().parse::<u64>().with_context(|| {
+                    format!(
+                        "The `{part}` group of branch pattern '{}' did not match a number in '{branch}'",
+                        rule.pattern
+                    )
+                })?;
+                if expected != actual {
+                    let major = captures.name("major").map_or("x", |m| m.as_str());
+                    let minor = captures.name("minor").map_or("x", |m| m.as_str());
+                    anyhow::bail!(
+                        "Branch '{branch}' may only release {major}.{minor}.x versions, by the branch pattern '{}', but the version is {version}",
+                        rule.pattern
+                    );
+                }
+            }
+
+            if let Some(requirement) = rule.version_req()? {
+                let release = semver::Version::new(version.major, version.minor, version.patch);
+                if !requirement.matches(&release) {
+                    anyhow::bail!(
+                        "Branch '{branch}' may only release versions matching '{requirement}', by the branch pattern '{}', but the version is {version}",
+                        rule.pattern
+                    );
+                }
+            }
+
+            return Ok(());
+        }
+
+        anyhow::bail!(
+            "Branch '{branch}' may not release, it does not match any of the configured branch patterns: {}",
+            self.branches
+                .iter()
+                .map(|rule| rule.pattern.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.cliff_config.trim().is_empty() {
+            anyhow::bail!("Invalid release configuration: `cliff-config` must not be empty");
+        }
+        if self.release_label.trim().is_empty() {
+            anyhow::bail!("Invalid release configuration: `release-label` must not be empty");
+        }
+        if self.tag_prefix.ends_with(|c: char| c.is_ascii_digit())
+            || !git2::Reference::is_valid_name(&format!("refs/tags/{}0.1.0", self.tag_prefix))
+        {
+            anyhow::bail!(
+                "Invalid release configuration: `tag-prefix` must be usable in a tag name and must not end with a digit, got: {:?}",
+                self.tag_prefix
+            );
+        }
+
+        if let Some((crate_name, _)) = self.artifacts.iter().find(|(_, bins)| bins.is_empty()) {
+            anyhow::bail!(
+                "Invalid release configuration: `artifacts` for crate '{crate_name}' must list at least one binary"
+            );
+        }
+
+        for rule in &self.branches {
+            rule.regex()
+                .and_then(|_| rule.version_req())
+                .context("Invalid release configuration in `branches`")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn config_error(error: anyhow::Error) -> ReleaseError {
+    into_release_error(error, |message| ReleaseError::Config { message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"configured\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+
+        // Defaults without any configuration.
+        assert_eq!(
+            ReleaseConfig::load(dir.path()).unwrap(),
+            ReleaseConfig::default()
+        );
+
+        std::fs::write(
+            dir.path().join(CONFIG_FILE),
+            "release-label = \"ship-it\"\ntag-prefix = \"release-\"\n",
+        )
+        .unwrap();
+        let config = ReleaseConfig::load(dir.path()).unwrap();
+        assert_eq!(config.release_label, "ship-it");
+        assert_eq!(config.cliff_config, "cliff.toml");
+        assert_eq!(config.release_tag_prefix(Some("a")), "a-release-");
+
+        let config = config
+            .with_overrides(ConfigOverrides {
+                cliff_config: Some("other.toml".to_string()),
+                release_label: Some("release-me".to_string()),
+                independent_versions: true,
+                branches: vec!["trunk".to_string()],
+                ..ConfigOverrides::default()
+            })
+            .unwrap();
+        assert_eq!(config.cliff_config, "other.toml");
+        assert_eq!(config.release_label, "release-me");
+        assert_eq!(config.tag_prefix, "release-");
+        assert!(config.independent_versions);
+        assert_eq!(config.branches, vec![BranchRule::new("trunk")]);
+        assert!(
+            config
+                .with_overrides(ConfigOverrides {
+                    tag_prefix: Some("v1".to_string()),
+                    ..ConfigOverrides::default()
+                })
+                .is_err()
+        );
+
+        // Artifacts are listed by crate, and must name at least one binary.
+        std::fs::write(
+            dir.path().join(CONFIG_FILE),
+            "[artifacts]\nconfigured = [\"tool\"]\n",
+        )
+        .unwrap();
+        let config = ReleaseConfig::load(dir.path()).unwrap();
+        assert_eq!(config.artifacts["configured"], vec!["tool".to_string()]);
+        std::fs::write(
+            dir.path().join(CONFIG_FILE),
+            "[artifacts]\nconfigured = []\n",
+        )
+        .unwrap();
+        assert!(ReleaseConfig::load(dir.path()).is_err());
+
+        // Unknown settings are rejected.
+        std::fs::write(dir.path().join(CONFIG_FILE), "release-lable = \"typo\"\n").unwrap();
+        assert!(ReleaseConfig::load(dir.path()).is_err());
+
+        // Configuration in both places is rejected.
+        std::fs::write(dir.path().join(CONFIG_FILE), "").unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"configured\"\nversion = \"0.1.0\"\n\n[package.metadata.release-util]\nindependent-versions = true\n",
+        )
+        .unwrap();
+        assert!(ReleaseConfig::load(dir.path()).is_err());
+
+        std::fs::remove_file(dir.path().join(CONFIG_FILE)).unwrap();
+        assert!(
+            ReleaseConfig::load(dir.path())
+                .unwrap()
+                .independent_versions
+        );
+    }
+
+    #[test]
+    fn branch_policy() {
+        let config = toml::from_str::<ReleaseConfig>(
+            r#"
+[[branches]]
+pattern = "main"
+versions = "<1.0.0"
+
+[[branches]]
+pattern = "release-(?<major>\\d+)\\.(?<minor>\\d+)"
+"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+
+        config.check_branch("main", &v("0.5.0-dev.1")).unwrap();
+        config.check_branch("release-0.2", &v("0.2.7")).unwrap();
+
+        let err = config.check_branch("main", &v("1.0.0")).unwrap_err();
+        assert!(err.to_string().contains("'<1.0.0'"), "{err}");
+        let err = config.check_branch("release-0.2", &v("0.3.0")).unwrap_err();
+        assert!(err.to_string().contains("0.2.x"), "{err}");
+        let err = config.check_branch("main-old", &v("0.1.0")).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err}");
+
+        // By default, only `main` and release branches, and the branches that start with them, may
+        // release.
+        let config = ReleaseConfig::default();
+        config.check_branch("main", &v("0.2.0")).unwrap();
+        config.check_branch("release-0.1", &v("0.1.4")).unwrap();
+        config.check_branch("release/0.1.x", &v("0.1.4")).unwrap();
+        config.check_branch("main-0.1", &v("0.1.4")).unwrap();
+        let err = config.check_branch("feature", &v("0.2.0")).unwrap_err();
+        assert!(err.to_string().contains("may not release"), "{err}");
+    }
+
+    #[test]
+    fn default_branches_match_the_publish_workflow() {
+        let readme = include_str!("../../../README.md");
+        let workflow = &readme[readme
+            .find("name: Publish release")
+            .expect("No publish workflow in the README")..];
+        let branches = workflow
+            .lines()
+            .skip_while(|line| line.trim() != "branches:")
+            .skip(1)
+            .map_while(|line| line.trim().strip_prefix("- "))
+            .collect::<Vec<_>>();
+        assert!(!branches.is_empty(), "No branches in the publish workflow");
+
+        let config = ReleaseConfig::default();
+        let version = semver::Version::new(0, 2, 0);
+        for branch in branches {
+            let branch = branch.replace('*', "0.2");
+            config.check_branch(&branch, &version).unwrap();
+        }
+    }
+}
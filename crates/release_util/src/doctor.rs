@@ -0,0 +1,280 @@
+//! Checks that the tools and environment that a release needs are available.
+//!
+//! Each check produces a [`Check`] rather than an error, so that every problem can be reported at
+//! once, before a release is started.
+
+use crate::config::{CONFIG_FILE, ReleaseConfig};
+use crate::forge::repository_from_remote;
+use crate::manifest::workspace_crates;
+use crate::registry::Registry;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The oldest `cargo` that supports edition 2024, and publishing several packages together.
+const MINIMUM_CARGO: &str = "1.90.0";
+/// The oldest `cargo-semver-checks` that supports the rustdoc format of edition 2024.
+const MINIMUM_SEMVER_CHECKS: &str = "0.40.0";
+
+/// The outcome of a single check.
+pub(crate) struct Check {
+    pub(crate) name: String,
+    pub(crate) passed: bool,
+    pub(crate) detail: String,
+    /// How to fix the problem, when the check failed.
+    pub(crate) hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            passed: true,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            passed: false,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Run every check against the repository in `dir`.
+pub(crate) fn run_checks(dir: impl AsRef<Path>) -> Vec<Check> {
+    let mut checks = vec![
+        check_tool(
+            "cargo",
+            &["--version"],
+            MINIMUM_CARGO,
+            "Install a newer Rust toolchain with `rustup update`",
+        ),
+        check_tool(
+            "cargo-semver-checks",
+            &["semver-checks", "--version"],
+            MINIMUM_SEMVER_CHECKS,
+            "Install it with `cargo install --locked cargo-semver-checks`",
+        ),
+        check_env_var(
+            "GH_TOKEN",
+            "Set GH_TOKEN to a token that can push tags and create releases",
+        ),
+    ];
+
+    checks.push(check_config(&dir));
+
+    let repository = match git2::Repository::open(&dir) {
+        Ok(repository) => repository,
+        Err(e) => {
+            checks.push(Check::fail(
+                "git repository",
+                e.message().to_string(),
+                "Run the command from the root of a git repository, or pass `--dir`",
+            ));
+            return checks;
+        }
+    };
+
+    checks.push(check_forge_repository(&repository));
+    checks.push(check_git_identity(&repository));
+    checks.extend(check_registries(&dir));
+
+    checks
+}
+
+/// Print the checks as a table, followed by the hints for any that failed.
+pub(crate) fn print_checks(checks: &[Check]) {
+    let width = checks
+        .iter()
+        .map(|c| c.name.len())
+        .chain(["Check".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!("{:<width$}  Status  Detail", "Check");
+    for check in checks {
+        let status = if check.passed { "pass" } else { "FAIL" };
+        println!("{:<width$}  {status:<6}  {}", check.name, check.detail);
+    }
+
+    let hints = checks
+        .iter()
+        .filter_map(|c| c.hint.as_ref().map(|hint| (&c.name, hint)))
+        .collect::<Vec<_>>();
+    if !hints.is_empty() {
+        println!();
+        println!("To fix:");
+        for (name, hint) in hints {
+            println!("  {name}: {hint}");
+        }
+    }
+}
+
+/// Check that a cargo command is installed, by asking it for its version.
+fn check_tool(name: &str, args: &[&str], minimum: &str, hint: &str) -> Check {
+    let output = match std::process::Command::new("cargo").args(args).output() {
+        Ok(output) if output.status.success() => output,
+        Ok(_) => return Check::fail(name, "not installed", hint),
+        Err(e) => return Check::fail(name, format!("failed to run cargo: {e}"), hint),
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some(version) = parse_tool_version(&stdout) else {
+        return Check::fail(
+            name,
+            format!("unrecognized version: {}", stdout.trim()),
+            hint,
+        );
+    };
+
+    let minimum = semver::Version::parse(minimum).expect("valid minimum version");
+    if version < minimum {
+        Check::fail(name, format!("{version} is older than {minimum}"), hint)
+    } else {
+        Check::pass(name, format!("{version}"))
+    }
+}
+
+/// Get the version from the output of `<tool> --version`, such as `cargo 1.85.0 (d73d2caf9 ...)`.
+fn parse_tool_version(output: &str) -> Option<semver::Version> {
+    let version = output.split_whitespace().nth(1)?;
+
+    semver::Version::parse(version).ok()
+}
+
+fn check_env_var(name: &str, hint: &str) -> Check {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => Check::pass(name, "set"),
+        _ => Check::fail(name, "not set", hint),
+    }
+}
+
+/// Check that the release configuration, if there is one, is valid.
+fn check_config(dir: impl AsRef<Path>) -> Check {
+    const NAME: &str = "release configuration";
+
+    match ReleaseConfig::load(dir) {
+        Ok(config) if config == ReleaseConfig::default() => Check::pass(NAME, "defaults"),
+        Ok(config) => Check::pass(
+            NAME,
+            format!(
+                "label '{}', tag prefix '{}'",
+                config.release_label, config.tag_prefix
+            ),
+        ),
+        Err(e) => Check::fail(
+            NAME,
+            format!("{e:#}"),
+            format!("Fix {CONFIG_FILE} or the `release-util` metadata in Cargo.toml"),
+        ),
+    }
+}
+
+/// Check that the forge repository can be found, from `GITHUB_REPOSITORY` or the `origin` remote.
+fn check_forge_repository(repository: &git2::Repository) -> Check {
+    const NAME: &str = "forge repository";
+
+    if let Ok(github_repository) = std::env::var("GITHUB_REPOSITORY") {
+        return Check::pass(NAME, format!("{github_repository} from GITHUB_REPOSITORY"));
+    }
+
+    match repository_from_remote(repository) {
+        Ok(name) => Check::pass(NAME, format!("{name} from the origin remote")),
+        Err(e) => Check::fail(
+            NAME,
+            format!("{e:#}"),
+            "Add an `origin` remote, set GITHUB_REPOSITORY, or pass `--repository` to `publish`",
+        ),
+    }
+}
+
+/// Check that tags can be signed and pushed, which needs a name and email.
+fn check_git_identity(repository: &git2::Repository) -> Check {
+    const NAME: &str = "git identity";
+
+    match repository.signature() {
+        Ok(signature) => match signature.email() {
+            Some(email) if !email.is_empty() => Check::pass(
+                NAME,
+                format!("{} <{email}>", signature.name().unwrap_or_default()),
+            ),
+            _ => Check::fail(
+                NAME,
+                "user.email is not set",
+                "Set it with `git config user.email <email>`",
+            ),
+        },
+        Err(e) => Check::fail(
+            NAME,
+            e.message().to_string(),
+            "Set `user.name` and `user.email` with `git config`",
+        ),
+    }
+}
+
+/// Check that each registry that crates are published to is configured and has a token.
+fn check_registries(dir: impl AsRef<Path>) -> Vec<Check> {
+    let crates = match workspace_crates(&dir) {
+        Ok(crates) => crates,
+        Err(e) => {
+            return vec![Check::fail(
+                "workspace",
+                format!("{e:#}"),
+                "Fix the `Cargo.toml` manifests in the workspace",
+            )];
+        }
+    };
+
+    let registries = crates
+        .into_iter()
+        .filter(|c| c.publish)
+        .map(|c| c.registry)
+        .collect::<BTreeSet<_>>();
+
+    let mut checks = Vec::new();
+    for registry in registries {
+        let name = format!("registry {}", registry.as_deref().unwrap_or("(default)"));
+        match Registry::for_crate(&dir, registry.as_deref()) {
+            Ok(found) if found.has_token() => {
+                checks.push(Check::pass(name, format!("{} with a token", found.name())))
+            }
+            Ok(found) => checks.push(Check::fail(
+                name,
+                format!("no token for {}", found.name()),
+                format!(
+                    "Run `cargo login --registry {}`, or set the token in `.cargo/config.toml` or the environment",
+                    found.name()
+                ),
+            )),
+            Err(e) => checks.push(Check::fail(
+                name,
+                format!("{e:#}"),
+                "Configure a sparse index for the registry in `.cargo/config.toml`",
+            )),
+        }
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_versions_of_tools() {
+        assert_eq!(
+            parse_tool_version("cargo 1.93.1 (083ac5135 2025-12-15)\n"),
+            Some(semver::Version::new(1, 93, 1))
+        );
+        assert_eq!(
+            parse_tool_version("cargo-semver-checks 0.45.0\n"),
+            Some(semver::Version::new(0, 45, 0))
+        );
+        assert_eq!(parse_tool_version("unknown"), None);
+    }
+}
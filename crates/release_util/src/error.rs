@@ -0,0 +1,285 @@
+//! The kinds of release failure that callers can tell apart.
+
+use crate::ReleaseType;
+use anyhow::Context;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// The error that the library functions return, by the kind of failure.
+///
+/// Every variant has a `message`, which describes the failure along with what was being done when
+/// it happened. Failures that are not one of the other kinds are [`ReleaseError::Other`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReleaseError {
+    /// A command line tool that the release needs is not installed.
+    ToolNotFound { tool: String, message: String },
+    /// A command line tool failed, with what it printed to stderr.
+    ToolFailed {
+        tool: String,
+        stderr: String,
+        message: String,
+    },
+    /// `cargo semver-checks` found changes that are not allowed in the planned release, with what
+    /// it printed to stderr.
+    SemverViolation {
+        release_type: ReleaseType,
+        stderr: String,
+        message: String,
+    },
+    /// A version is not valid for the release, such as a forced version that goes backwards, or a
+    /// crate that is not at the release version.
+    InvalidVersion { version: String, message: String },
+    /// A release tag already exists, and is not where the release would put it.
+    TagConflict { tag: String, message: String },
+    /// A registry could not be checked for the crates that are already published.
+    Registry { message: String },
+    /// A request to the forge failed.
+    Forge { message: String },
+    /// The branch policy does not permit releasing from the current branch.
+    BranchPolicy { branch: String, message: String },
+    /// The release configuration could not be loaded, or is not valid.
+    Config { message: String },
+    /// A release tag could not be signed, or its signature could not be verified.
+    Signing { message: String },
+    /// Any other failure.
+    Other { message: String },
+}
+
+impl ReleaseError {
+    /// The exit code that the binary uses for this kind of failure.
+    ///
+    /// Invalid command line arguments exit with `2`.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ReleaseError::Other { .. } => 1,
+            ReleaseError::ToolNotFound { .. } => 3,
+            ReleaseError::ToolFailed { .. } => 4,
+            ReleaseError::SemverViolation { .. } => 5,
+            ReleaseError::InvalidVersion { .. } => 6,
+            ReleaseError::TagConflict { .. } => 7,
+            ReleaseError::Registry { .. } => 8,
+            ReleaseError::Forge { .. } => 9,
+            ReleaseError::BranchPolicy { .. } => 10,
+            ReleaseError::Config { .. } => 11,
+            ReleaseError::Signing { .. } => 12,
+        }
+    }
+
+    /// The description of the failure.
+    pub fn message(&self) -> &str {
+        match self {
+            ReleaseError::ToolNotFound { message, .. }
+            | ReleaseError::ToolFailed { message, .. }
+            | ReleaseError::SemverViolation { message, .. }
+            | ReleaseError::InvalidVersion { message, .. }
+            | ReleaseError::TagConflict { message, .. }
+            | ReleaseError::Registry { message }
+            | ReleaseError::Forge { message }
+            | ReleaseError::BranchPolicy { message, .. }
+            | ReleaseError::Config { message }
+            | ReleaseError::Signing { message }
+            | ReleaseError::Other { message } => message,
+        }
+    }
+
+    fn with_message(mut self, new_message: String) -> Self {
+        match &mut self {
+            ReleaseError::ToolNotFound { message, .. }
+            | ReleaseError::ToolFailed { message, .. }
+            | ReleaseError::SemverViolation { message, .. }
+            | ReleaseError::InvalidVersion { message, .. }
+            | ReleaseError::TagConflict { message, .. }
+            | ReleaseError::Registry { message }
+            | ReleaseError::Forge { message }
+            | ReleaseError::BranchPolicy { message, .. }
+            | ReleaseError::Config { message }
+            | ReleaseError::Signing { message }
+            | ReleaseError::Other { message } => *message = new_message,
+        }
+        self
+    }
+}
+
+impl Display for ReleaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for ReleaseError {}
+
+impl From<anyhow::Error> for ReleaseError {
+    fn from(error: anyhow::Error) -> Self {
+        into_release_error(error, |message| ReleaseError::Other { message })
+    }
+}
+
+/// Turn an error into a [`ReleaseError`], with the whole chain of context as its message.
+///
+/// The kind is that of the outermost `ReleaseError` in the chain, unless that is
+/// [`ReleaseError::Other`] or there is none, when `kind` makes the error from the message.
+pub(crate) fn into_release_error(
+    error: anyhow::Error,
+    kind: impl FnOnce(String) -> ReleaseError,
+) -> ReleaseError {
+    let message = format!("{error:#}");
+    match error.downcast_ref::<ReleaseError>() {
+        Some(ReleaseError::Other { .. }) | None => kind(message),
+        Some(release_error) => release_error.clone().with_message(message),
+    }
+}
+
+/// Turn an error from starting a command line tool into a [`ReleaseError::ToolNotFound`] when the
+/// tool is missing.
+pub(crate) fn tool_error(tool: &str, error: std::io::Error) -> anyhow::Error {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        ReleaseError::ToolNotFound {
+            tool: tool.to_string(),
+            message: format!("`{tool}` is not installed, or is not on the PATH"),
+        }
+        .into()
+    } else {
+        anyhow::Error::new(error).context(format!("Failed to run `{tool}`"))
+    }
+}
+
+/// Turn the captured output of a command line tool that failed into a
+/// [`ReleaseError::ToolFailed`].
+pub(crate) fn tool_failed(tool: &str, output: &std::process::Output) -> anyhow::Error {
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let message = match stderr_tail(&stderr) {
+        tail if tail.is_empty() => format!("`{tool}` failed ({})", output.status),
+        tail => format!("`{tool}` failed ({}): {tail}", output.status),
+    };
+    ReleaseError::ToolFailed {
+        tool: tool.to_string(),
+        stderr,
+        message,
+    }
+    .into()
+}
+
+/// The last lines of what a tool printed to stderr, which is enough to say why it failed without
+/// repeating all of a long build log.
+pub(crate) fn stderr_tail(stderr: &str) -> String {
+    const LINES: usize = 20;
+
+    let lines = stderr.trim().lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(LINES)..].join("\n")
+}
+
+/// Run a command line tool that may print a lot to stderr, such as cargo.
+///
+/// Its stderr is shown as it runs, and is also captured in the output, so that a failure can
+/// include it. Its stdout is only captured if the command pipes it.
+pub(crate) fn run_tool(
+    tool: &str,
+    command: &mut std::process::Command,
+) -> anyhow::Result<std::process::Output> {
+    let mut child = command
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| tool_error(tool, e))?;
+
+    let stderr = child.stderr.take().context("No stderr to read")?;
+    // Read on another thread, so that a tool that fills its stdout pipe is not blocked.
+    let tee = std::thread::spawn(move || -> std::io::Result<Vec<u8>> {
+        let mut reader = std::io::BufReader::new(stderr);
+        let mut captured = Vec::new();
+        loop {
+            let start = captured.len();
+            if reader.read_until(b'\n', &mut captured)? == 0 {
+                return Ok(captured);
+            }
+            std::io::stderr().write_all(&captured[start..])?;
+        }
+    });
+
+    let mut output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run `{tool}`"))?;
+    output.stderr = tee
+        .join()
+        .map_err(|_| anyhow::anyhow!("Failed to read the stderr of `{tool}`"))?
+        .with_context(|| format!("Failed to read the stderr of `{tool}`"))?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_the_kind_and_context_of_an_error() {
+        let err = Err::<(), _>(ReleaseError::Registry {
+            message: "Failed to check crate: a@0.1.0".to_string(),
+        })
+        .context("Publishing failed")
+        .unwrap_err();
+
+        let release_error = ReleaseError::from(err);
+        assert_eq!(release_error.exit_code(), 8);
+        assert_eq!(
+            release_error,
+            ReleaseError::Registry {
+                message: "Publishing failed: Failed to check crate: a@0.1.0".to_string()
+            }
+        );
+
+        let missing = ReleaseError::from(
+            tool_error("gpg", std::io::ErrorKind::NotFound.into()).context("Failed to sign"),
+        );
+        assert_eq!(
+            missing,
+            ReleaseError::ToolNotFound {
+                tool: "gpg".to_string(),
+                message: "Failed to sign: `gpg` is not installed, or is not on the PATH"
+                    .to_string()
+            }
+        );
+        let denied = ReleaseError::from(tool_error(
+            "gpg",
+            std::io::ErrorKind::PermissionDenied.into(),
+        ));
+        assert_eq!(denied.exit_code(), 1);
+
+        let config = into_release_error(anyhow::anyhow!("Invalid TOML"), |message| {
+            ReleaseError::Config { message }
+        });
+        assert_eq!(config.exit_code(), 11);
+        assert_eq!(config.to_string(), "Invalid TOML");
+
+        let other = anyhow::Error::new(ReleaseError::Other {
+            message: "Invalid key".to_string(),
+        })
+        .context("Failed to sign");
+        let signing = into_release_error(other, |message| ReleaseError::Signing { message });
+        assert_eq!(
+            signing,
+            ReleaseError::Signing {
+                message: "Failed to sign: Invalid key".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn capture_stderr_of_a_failed_tool() {
+        let mut command = std::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo out; echo broken >&2; exit 3")
+            .stdout(std::process::Stdio::piped());
+        let output = run_tool("sh", &mut command).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+        let ReleaseError::ToolFailed {
+            stderr, message, ..
+        } = ReleaseError::from(tool_failed("sh", &output))
+        else {
+            panic!("Expected a tool failure");
+        };
+        assert_eq!(stderr, "broken\n");
+        assert_eq!(message, "`sh` failed (exit status: 3): broken");
+    }
+}
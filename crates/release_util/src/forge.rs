@@ -0,0 +1,202 @@
+//! Access to the forge that hosts the repository, such as GitHub.
+//!
+//! The release process needs to find the pull request that a commit came from, check its labels
+//! and create releases. Those operations are described by the [`Forge`] trait, so that the
+//! backend can be picked at runtime with [`connect`]. The backends report failures as
+//! [`anyhow::Error`], which the release reports as a [`ReleaseError::Forge`].
+
+use crate::error::{ReleaseError, into_release_error};
+use anyhow::Context;
+use std::path::Path;
+
+mod gitea;
+mod github;
+
+pub use gitea::GiteaForge;
+pub use github::GitHubForge;
+
+/// A pull request on a forge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    /// The pull request number.
+    pub number: u64,
+    /// The title of the pull request.
+    pub title: String,
+    /// The web URL of the pull request.
+    pub url: String,
+}
+
+/// A release on a forge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// The forge's identifier for the release.
+    pub id: u64,
+    /// The tag that the release was created for.
+    pub tag: String,
+    /// The web URL of the release.
+    pub url: String,
+}
+
+/// The details of a release to create.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewRelease {
+    /// The existing tag to create the release for.
+    pub tag: String,
+    /// The title of the release.
+    pub title: String,
+    /// The release notes. If `None`, the forge is asked to generate them, if it can.
+    pub notes: Option<String>,
+    /// Whether to mark the release as a pre-release.
+    pub pre_release: bool,
+}
+
+/// The details of a pull request to open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewPullRequest {
+    /// The title of the pull request.
+    pub title: String,
+    /// The description of the pull request.
+    pub body: String,
+    /// The branch that contains the changes.
+    pub head: String,
+    /// The branch to merge the changes into.
+    pub base: String,
+}
+
+/// The operations that the release process needs from a forge.
+pub trait Forge {
+    /// The repository on the forge, as `owner/name`.
+    fn repository(&self) -> &str;
+
+    /// Find the merged pull request that introduced the given commit, if there is one.
+    fn find_merged_pull_request(&self, commit: &str) -> anyhow::Result<Option<PullRequest>>;
+
+    /// Get the names of the labels on a pull request.
+    fn pull_request_labels(&self, number: u64) -> anyhow::Result<Vec<String>>;
+
+    /// Create a release for an existing tag.
+    fn create_release(&self, release: &NewRelease) -> anyhow::Result<Release>;
+
+    /// Upload a file as an asset of an existing release.
+    ///
+    /// The asset is named after the file.
+    fn upload_release_asset(&self, release: &Release, path: &Path) -> anyhow::Result<()>;
+
+    /// Open a pull request.
+    fn open_pull_request(&self, pull_request: &NewPullRequest) -> anyhow::Result<PullRequest>;
+}
+
+/// The forge backends that are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeKind {
+    /// GitHub, or GitHub Enterprise with a custom API URL.
+    GitHub,
+    /// A self-hosted Gitea instance, which requires the URL of the instance.
+    Gitea,
+}
+
+/// Connect to the forge that hosts `repository`, given as `owner/name`.
+///
+/// The `api_url` overrides the default API location of the forge, for self-hosted instances. For
+/// Gitea, which has no default, it is the URL of the instance, such as `http://localhost:3000`.
+pub fn connect(
+    kind: ForgeKind,
+    api_url: Option<&str>,
+    repository: &str,
+    token: &str,
+) -> Result<Box<dyn Forge>, ReleaseError> {
+    let forge = || -> anyhow::Result<Box<dyn Forge>> {
+        Ok(match kind {
+            ForgeKind::GitHub => Box::new(GitHubForge::new(api_url, repository, token)?),
+            ForgeKind::Gitea => Box::new(GiteaForge::new(
+                api_url.context("The URL of the Gitea instance is required")?,
+                repository,
+                token,
+            )?),
+        })
+    };
+
+    forge().map_err(|e| into_release_error(e, |message| ReleaseError::Forge { message }))
+}
+
+/// Get the `owner/name` of the repository from the URL of its `origin` remote.
+pub fn repository_from_remote(repository: &git2::Repository) -> Result<String, ReleaseError> {
+    let remote = repository
+        .find_remote("origin")
+        .context("Failed to find remote 'origin'")?;
+    let url = remote.url().context("Remote 'origin' has an invalid URL")?;
+
+    Ok(repository_from_url(url)?)
+}
+
+/// Get the `owner/name` of a repository from an HTTP(S) or SSH clone URL.
+fn repository_from_url(url: &str) -> anyhow::Result<String> {
+    let path = match url::Url::parse(url) {
+        Ok(url) => url.path().to_string(),
+        // SCP-like SSH URLs, such as `git@github.com:owner/name.git`, are not valid URLs.
+        Err(_) => url
+            .split_once(':')
+            .map(|(_, path)| path.to_string())
+            .with_context(|| format!("Unsupported remote URL: {url}"))?,
+    };
+
+    let mut parts = path
+        .trim_matches('/')
+        .trim_end_matches(".git")
+        .rsplitn(2, '/');
+    let name = parts.next().filter(|name| !name.is_empty());
+    let owner = parts
+        .next()
+        .and_then(|owner| owner.rsplit('/').next())
+        .filter(|owner| !owner.is_empty());
+
+    match (owner, name) {
+        (Some(owner), Some(name)) => Ok(format!("{owner}/{name}")),
+        _ => anyhow::bail!("Could not find the repository owner and name in: {url}"),
+    }
+}
+
+/// Turn a failed API response into an error that includes the forge's explanation.
+fn check_response(
+    response: reqwest::blocking::Response,
+) -> anyhow::Result<reqwest::blocking::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let url = response.url().clone();
+    let body = response.text().unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value.get("message")?.as_str().map(ToString::to_string))
+        .unwrap_or(body);
+
+    anyhow::bail!("Request to {url} failed with status {status}: {message}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_from_remote_urls() {
+        assert_eq!(
+            repository_from_url("https://github.com/holochain/holochain.git").unwrap(),
+            "holochain/holochain"
+        );
+        assert_eq!(
+            repository_from_url("http://localhost:3000/gituser/project").unwrap(),
+            "gituser/project"
+        );
+        assert_eq!(
+            repository_from_url("git@github.com:holochain/holochain.git").unwrap(),
+            "holochain/holochain"
+        );
+        assert_eq!(
+            repository_from_url("ssh://git@github.com/holochain/holochain.git").unwrap(),
+            "holochain/holochain"
+        );
+        assert!(repository_from_url("https://github.com/").is_err());
+    }
+}
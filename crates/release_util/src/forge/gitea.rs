@@ -0,0 +1,199 @@
+//! A [`Forge`] backed by the Gitea REST API.
+
+use super::{Forge, NewPullRequest, NewRelease, PullRequest, Release, check_response};
+use anyhow::Context;
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::blocking::multipart::Form;
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, HeaderValue, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A Gitea repository, accessed through the REST API.
+pub struct GiteaForge {
+    client: Client,
+    api_url: String,
+    repository: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaPullRequest {
+    number: u64,
+    title: String,
+    html_url: String,
+    #[serde(default)]
+    merged: bool,
+}
+
+impl From<GiteaPullRequest> for PullRequest {
+    fn from(value: GiteaPullRequest) -> Self {
+        PullRequest {
+            number: value.number,
+            title: value.title,
+            url: value.html_url,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GiteaLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaRelease {
+    id: u64,
+    tag_name: String,
+    html_url: String,
+}
+
+#[derive(Serialize)]
+struct CreateRelease<'a> {
+    tag_name: &'a str,
+    name: &'a str,
+    body: &'a str,
+    prerelease: bool,
+}
+
+#[derive(Serialize)]
+struct CreatePullRequest<'a> {
+    title: &'a str,
+    body: &'a str,
+    head: &'a str,
+    base: &'a str,
+}
+
+impl GiteaForge {
+    /// Create a client for the `owner/name` repository on the Gitea instance at `url`.
+    ///
+    /// The `url` is the address of the Gitea web interface, such as `http://localhost:3000`.
+    pub fn new(url: &str, repository: &str, token: &str) -> anyhow::Result<Self> {
+        let mut authorization =
+            HeaderValue::from_str(&format!("token {token}")).context("Invalid Gitea token")?;
+        authorization.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization);
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_static("holochain_release_util"),
+        );
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .build()
+            .context("Failed to create Gitea client")?;
+
+        Ok(GiteaForge {
+            client,
+            api_url: format!("{}/api/v1", url.trim_end_matches('/')),
+            repository: repository.to_string(),
+        })
+    }
+
+    fn repo_url(&self, path: &str) -> String {
+        format!("{}/repos/{}/{path}", self.api_url, self.repository)
+    }
+}
+
+impl Forge for GiteaForge {
+    fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    fn find_merged_pull_request(&self, commit: &str) -> anyhow::Result<Option<PullRequest>> {
+        // Gitea looks the pull request up by its merge commit.
+        let response = self
+            .client
+            .get(self.repo_url(&format!("commits/{commit}/pull")))
+            .send()
+            .context("Failed to find pull request for commit")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let pull_request = check_response(response)?
+            .json::<GiteaPullRequest>()
+            .context("Unexpected pull request from Gitea")?;
+
+        Ok(pull_request.merged.then(|| pull_request.into()))
+    }
+
+    fn pull_request_labels(&self, number: u64) -> anyhow::Result<Vec<String>> {
+        let response = self
+            .client
+            .get(self.repo_url(&format!("issues/{number}/labels")))
+            .send()
+            .context("Failed to list pull request labels")?;
+        let labels = check_response(response)?
+            .json::<Vec<GiteaLabel>>()
+            .context("Unexpected label list from Gitea")?;
+
+        Ok(labels.into_iter().map(|label| label.name).collect())
+    }
+
+    fn create_release(&self, release: &NewRelease) -> anyhow::Result<Release> {
+        // Gitea cannot generate release notes, so the release is created without any.
+        let response = self
+            .client
+            .post(self.repo_url("releases"))
+            .json(&CreateRelease {
+                tag_name: &release.tag,
+                name: &release.title,
+                body: release.notes.as_deref().unwrap_or_default(),
+                prerelease: release.pre_release,
+            })
+            .send()
+            .context("Failed to create release")?;
+        let created = check_response(response)?
+            .json::<GiteaRelease>()
+            .context("Unexpected release from Gitea")?;
+
+        Ok(Release {
+            id: created.id,
+            tag: created.tag_name,
+            url: created.html_url,
+        })
+    }
+
+    fn upload_release_asset(&self, release: &Release, path: &Path) -> anyhow::Result<()> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Invalid asset file name: {}", path.display()))?;
+        let form = Form::new()
+            .file("attachment", path)
+            .with_context(|| format!("Failed to read asset: {}", path.display()))?;
+
+        let response = self
+            .client
+            .post(self.repo_url(&format!("releases/{}/assets", release.id)))
+            .query(&[("name", name)])
+            .multipart(form)
+            .send()
+            .context("Failed to upload release asset")?;
+        check_response(response)?;
+
+        Ok(())
+    }
+
+    fn open_pull_request(&self, pull_request: &NewPullRequest) -> anyhow::Result<PullRequest> {
+        let response = self
+            .client
+            .post(self.repo_url("pulls"))
+            .json(&CreatePullRequest {
+                title: &pull_request.title,
+                body: &pull_request.body,
+                head: &pull_request.head,
+                base: &pull_request.base,
+            })
+            .send()
+            .context("Failed to open pull request")?;
+        let created = check_response(response)?
+            .json::<GiteaPullRequest>()
+            .context("Unexpected pull request from Gitea")?;
+
+        Ok(created.into())
+    }
+}
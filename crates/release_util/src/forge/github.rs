@@ -0,0 +1,253 @@
+//! A [`Forge`] backed by the GitHub REST API.
+
+use super::{Forge, NewPullRequest, NewRelease, PullRequest, Release, check_response};
+use anyhow::Context;
+use reqwest::blocking::Client;
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// A GitHub repository, accessed through the REST API.
+pub struct GitHubForge {
+    client: Client,
+    api_url: String,
+    repository: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubPullRequest {
+    number: u64,
+    title: String,
+    html_url: String,
+    merged_at: Option<String>,
+}
+
+impl From<GitHubPullRequest> for PullRequest {
+    fn from(value: GitHubPullRequest) -> Self {
+        PullRequest {
+            number: value.number,
+            title: value.title,
+            url: value.html_url,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    id: u64,
+    tag_name: String,
+    html_url: String,
+    upload_url: String,
+}
+
+#[derive(Serialize)]
+struct CreateRelease<'a> {
+    tag_name: &'a str,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<&'a str>,
+    generate_release_notes: bool,
+    prerelease: bool,
+}
+
+#[derive(Serialize)]
+struct CreatePullRequest<'a> {
+    title: &'a str,
+    body: &'a str,
+    head: &'a str,
+    base: &'a str,
+}
+
+impl GitHubForge {
+    /// Create a client for the `owner/name` repository on GitHub.
+    ///
+    /// The `api_url` defaults to `https://api.github.com`, and can be set for GitHub Enterprise.
+    pub fn new(api_url: Option<&str>, repository: &str, token: &str) -> anyhow::Result<Self> {
+        let mut authorization =
+            HeaderValue::from_str(&format!("Bearer {token}")).context("Invalid GitHub token")?;
+        authorization.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization);
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.github+json"),
+        );
+        headers.insert(
+            "X-GitHub-Api-Version",
+            HeaderValue::from_static("2022-11-28"),
+        );
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_static("holochain_release_util"),
+        );
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .build()
+            .context("Failed to create GitHub client")?;
+
+        Ok(GitHubForge {
+            client,
+            api_url: api_url
+                .unwrap_or(DEFAULT_API_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            repository: repository.to_string(),
+        })
+    }
+
+    fn repo_url(&self, path: &str) -> String {
+        format!("{}/repos/{}/{path}", self.api_url, self.repository)
+    }
+}
+
+impl Forge for GitHubForge {
+    fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    fn find_merged_pull_request(&self, commit: &str) -> anyhow::Result<Option<PullRequest>> {
+        let response = self
+            .client
+            .get(self.repo_url(&format!("commits/{commit}/pulls")))
+            .send()
+            .context("Failed to list pull requests for commit")?;
+        let pull_requests = check_response(response)?
+            .json::<Vec<GitHubPullRequest>>()
+            .context("Unexpected pull request list from GitHub")?;
+
+        let mut merged = pull_requests
+            .into_iter()
+            .filter(|pr| pr.merged_at.is_some())
+            .collect::<Vec<_>>();
+
+        if merged.len() > 1 {
+            eprintln!(
+                "Found {} merged pull requests for commit {commit}, cannot pick one",
+                merged.len()
+            );
+            return Ok(None);
+        }
+
+        Ok(merged.pop().map(Into::into))
+    }
+
+    fn pull_request_labels(&self, number: u64) -> anyhow::Result<Vec<String>> {
+        let response = self
+            .client
+            .get(self.repo_url(&format!("issues/{number}/labels")))
+            .query(&[("per_page", "100")])
+            .send()
+            .context("Failed to list pull request labels")?;
+        let labels = check_response(response)?
+            .json::<Vec<GitHubLabel>>()
+            .context("Unexpected label list from GitHub")?;
+
+        Ok(labels.into_iter().map(|label| label.name).collect())
+    }
+
+    fn create_release(&self, release: &NewRelease) -> anyhow::Result<Release> {
+        let response = self
+            .client
+            .post(self.repo_url("releases"))
+            .json(&CreateRelease {
+                tag_name: &release.tag,
+                name: &release.title,
+                body: release.notes.as_deref(),
+                generate_release_notes: release.notes.is_none(),
+                prerelease: release.pre_release,
+            })
+            .send()
+            .context("Failed to create release")?;
+        let created = check_response(response)?
+            .json::<GitHubRelease>()
+            .context("Unexpected release from GitHub")?;
+
+        Ok(Release {
+            id: created.id,
+            tag: created.tag_name,
+            url: created.html_url,
+        })
+    }
+
+    fn upload_release_asset(&self, release: &Release, path: &Path) -> anyhow::Result<()> {
+        // Assets are uploaded to a different host, which GitHub provides as a URL template.
+        let response = self
+            .client
+            .get(self.repo_url(&format!("releases/{}", release.id)))
+            .send()
+            .context("Failed to get release")?;
+        let existing = check_response(response)?
+            .json::<GitHubRelease>()
+            .context("Unexpected release from GitHub")?;
+        let upload_url = upload_url(&existing.upload_url);
+
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Invalid asset file name: {}", path.display()))?;
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read asset: {}", path.display()))?;
+
+        let response = self
+            .client
+            .post(upload_url)
+            .query(&[("name", name)])
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(content)
+            .send()
+            .context("Failed to upload release asset")?;
+        check_response(response)?;
+
+        Ok(())
+    }
+
+    fn open_pull_request(&self, pull_request: &NewPullRequest) -> anyhow::Result<PullRequest> {
+        let response = self
+            .client
+            .post(self.repo_url("pulls"))
+            .json(&CreatePullRequest {
+                title: &pull_request.title,
+                body: &pull_request.body,
+                head: &pull_request.head,
+                base: &pull_request.base,
+            })
+            .send()
+            .context("Failed to open pull request")?;
+        let created = check_response(response)?
+            .json::<GitHubPullRequest>()
+            .context("Unexpected pull request from GitHub")?;
+
+        Ok(created.into())
+    }
+}
+
+/// Remove the query template, like `{?name,label}`, from a GitHub upload URL.
+fn upload_url(template: &str) -> &str {
+    template.split_once('{').map_or(template, |(url, _)| url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_upload_url_template() {
+        assert_eq!(
+            upload_url("https://uploads.github.com/repos/o/r/releases/1/assets{?name,label}"),
+            "https://uploads.github.com/repos/o/r/releases/1/assets"
+        );
+        assert_eq!(
+            upload_url("https://uploads.github.com/repos/o/r/releases/1/assets"),
+            "https://uploads.github.com/repos/o/r/releases/1/assets"
+        );
+    }
+}
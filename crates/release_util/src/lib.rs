@@ -1,106 +1,674 @@
+use crate::artifacts::{build_artifacts, check_artifact_crates};
+use crate::cliff::CliffContext;
+use crate::forge::Forge;
+use crate::manifest::{WorkspaceCrate, check_inheritance, include_in_package, workspace_crates};
 use crate::prepare_release::{
-    generate_changelog, get_next_version, get_released_version_tag, run_semver_checks, set_version,
+    check_forced_version, emit_warning, get_bumped_version_tag, get_next_pre_release_tag,
+    get_next_version, run_semver_checks, set_independent_version, set_version, write_changelog,
+};
+use crate::publish_release::{
+    CrateStatus, ExistingTag, PublishSummary, check_existing_tag, check_release_versions,
+    create_release, is_releasable_change, publish, publish_dry_run, publish_status, release_notes,
+    report_release, report_tag,
+};
+use crate::signing::{TagSigner, TagVerifier, verify_tag};
+use crate::utils::{
+    GitRemote, delete_tag, get_current_version, get_current_version_from_cargo_toml, push_tag, tag,
 };
-use crate::publish_release::{create_gh_release, is_releasable_change, publish};
-use crate::utils::{get_current_version_from_cargo_toml, get_revision_for_tag, push_tag, tag};
 use anyhow::Context;
 use std::fs::read_to_string;
 use std::path::Path;
 
+mod artifacts;
+mod cliff;
+mod config;
+mod doctor;
+mod error;
+pub mod forge;
+mod manifest;
+mod plan;
 mod prepare_release;
 mod publish_release;
+mod registry;
+pub mod signing;
 pub mod utils;
 
+pub use crate::config::{BranchRule, CONFIG_FILE, ConfigOverrides, ReleaseConfig};
+pub use crate::error::ReleaseError;
+use crate::plan::CrateChangelog;
+pub use crate::plan::{
+    BumpReason, CommitGroup, PlannedCommit, PreRelease, ReleasePlan, ReleaseType, SemverBaseline,
+    VersionOptions,
+};
+pub use crate::publish_release::{PublishOptions, PublishOutcome};
+
+/// The default label that marks a pull request as releasable.
 pub const RELEASE_LABEL: &str = "hra-release";
 
 /// Prepares changes for the next release.
 ///
-/// - Runs semver checks on the current branch to ensure it is releasable with
-///   the requested configuration.
-/// - Generates a changelog using `git-cliff` based on the provided configuration.
-/// - Sets the version in the `Cargo.toml` files to the next version determined by `git-cliff`.
+/// - Plans the release with [`plan_release`], which picks the next version using `git-cliff-core`.
+/// - Applies the plan with [`execute_release_plan`], which runs semver checks, generates the
+///   changelog and sets the version in the `Cargo.toml` files.
+///
+/// The repository's [`ReleaseConfig`] is loaded from `dir`, and the [`ConfigOverrides`] take
+/// precedence over it.
+///
+/// The [`VersionOptions`] can override the version that is picked:
+/// - A forced version must not already be tagged, and must come after the latest release on the
+///   current branch. A warning is printed if it skips past the next version.
+/// - With a pre-release channel, the next pre-release on that channel is picked, such as
+///   `0.5.0-dev.4` after `0.5.0-dev.3`.
+/// - With a bump kind, that bump is applied to the previous release on the current branch, such
+///   as `0.5.0` after `0.4.2` for a minor bump.
+///
+/// With independent versions, each publishable crate in the workspace is prepared separately,
+/// using its own `<crate>-vX.Y.Z` tags and only the commits that touch its directory. Crates
+/// without unreleased changes are skipped.
 pub fn prepare_release(
     dir: impl AsRef<Path>,
-    cliff_config: String,
-    force_version: Option<String>,
+    overrides: ConfigOverrides,
+    version: VersionOptions,
     skip_semver_checks: bool,
     i_am_so_sorry_but_my_features_clash: bool,
-) -> anyhow::Result<()> {
+) -> Result<(), ReleaseError> {
+    let plans = plan_release(&dir, overrides, version, skip_semver_checks)?;
+
+    for plan in &plans {
+        print!("{plan}");
+    }
+
+    execute_release_plan(&dir, &plans, i_am_so_sorry_but_my_features_clash)?;
+
+    if skip_semver_checks {
+        emit_warning(
+            "Semver Checks Skipped",
+            "Semver checks were skipped for this release. Ensure the version bump is intentional.",
+        );
+    }
+
+    Ok(())
+}
+
+/// Plans the next release, without changing anything in the repository.
+///
+/// Returns a single plan for the workspace, or with independent versions, one plan for each
+/// publishable crate that has unreleased changes. Fails if there is nothing to release, or if a
+/// publishable crate does not inherit its fields from the workspace, as for [`check_workspace`].
+///
+/// The [`ConfigOverrides`] take precedence over the [`ReleaseConfig`]. The [`VersionOptions`] are
+/// checked as for [`prepare_release`].
+pub fn plan_release(
+    dir: impl AsRef<Path>,
+    overrides: ConfigOverrides,
+    version: VersionOptions,
+    skip_semver_checks: bool,
+) -> Result<Vec<ReleasePlan>, ReleaseError> {
     let repository = git2::Repository::open(&dir).context("Failed to open git repository")?;
+    let config = ReleaseConfig::load(&dir)?.with_overrides(overrides)?;
+    let VersionOptions {
+        force_version,
+        allow_version_skip,
+        pre_release,
+        bump,
+    } = version;
 
-    let force_tag = input_version_to_version_tag(force_version)?;
+    let issues = check_inheritance(&dir, config.independent_versions, false)?;
+    if !issues.is_empty() {
+        return Err(ReleaseError::Config {
+            message: format!(
+                "Publishable crates must inherit from `[workspace.package]`, run `check-workspace --fix` to fix:\n{}",
+                issues
+                    .iter()
+                    .map(|issue| format!("  {issue}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        });
+    }
 
-    // Generate the changelog and check what version it chose.
-    generate_changelog(&dir, &cliff_config, &force_tag)?;
-    let next_version_tag = get_next_version(&dir, &cliff_config, &force_tag)?;
+    let tag_prefix = config.release_tag_prefix(None);
+    let force_tag = input_version_to_version_tag(force_version, &tag_prefix)?;
+    let choices = [force_tag.is_some(), pre_release.is_some(), bump.is_some()];
+    if choices.into_iter().filter(|chosen| *chosen).count() > 1 {
+        return Err(anyhow::anyhow!(
+            "Only one of a forced version, a pre-release channel or a bump kind can be given"
+        )
+        .into());
+    }
+    if let Some(force_tag) = &force_tag {
+        check_forced_version(&repository, &tag_prefix, force_tag, allow_version_skip)?;
+    }
 
-    // Set the version in the Cargo.toml files.
-    set_version(&dir, &next_version_tag)?;
+    // Pick the next version tag when it is chosen by the options rather than by `git-cliff`. This
+    // is `None` when there are no unreleased changes.
+    let chosen_tag = |tag_prefix: &str, crate_scope| -> anyhow::Result<Option<String>> {
+        let tag = match (pre_release, bump) {
+            (Some(channel), _) => get_next_pre_release_tag(
+                &dir,
+                &config.cliff_config,
+                tag_prefix,
+                crate_scope,
+                channel,
+            )?,
+            (None, Some(bump)) => {
+                let tag = get_bumped_version_tag(
+                    &dir,
+                    &config.cliff_config,
+                    tag_prefix,
+                    crate_scope,
+                    bump,
+                )?;
+                if let Some(tag) = &tag {
+                    check_forced_version(&repository, tag_prefix, tag, true)?;
+                }
+                tag
+            }
+            (None, None) => None,
+        };
 
+        Ok(tag)
+    };
+    let chosen_reason = match (pre_release, bump) {
+        (Some(_), _) => Some(BumpReason::PreRelease),
+        (None, Some(bump)) => Some(BumpReason::Requested(bump)),
+        (None, None) => None,
+    };
+
+    let publishable_crates = workspace_crates(&dir)?
+        .into_iter()
+        .filter(|c| c.publish)
+        .collect::<Vec<_>>();
+
+    if !config.independent_versions {
+        let force_tag = match chosen_reason {
+            Some(_) => chosen_tag(&tag_prefix, None)?,
+            None => force_tag,
+        };
+
+        // Process the unreleased changes once, then use that for every part of the plan.
+        let context = CliffContext::load(
+            &dir,
+            &config.cliff_config,
+            &tag_prefix,
+            &force_tag,
+            false,
+            None,
+        )?;
+        get_next_version(&context)?;
+
+        let mut plan = ReleasePlan::from_context(
+            &repository,
+            &context,
+            &tag_prefix,
+            None,
+            publishable_crates.iter().map(|c| c.name.clone()).collect(),
+            force_tag.is_some(),
+            skip_semver_checks,
+        )?;
+        if let Some(reason) = chosen_reason {
+            plan.bump_reason = reason;
+        }
+        check_branch_policy(&repository, &config, &plan.next_version)?;
+
+        if config.crate_changelogs {
+            plan.crate_changelogs =
+                plan_crate_changelogs(&dir, &config, &plan.next_tag, &publishable_crates)?;
+        }
+
+        return Ok(vec![plan]);
+    }
+
+    if force_tag.is_some() {
+        return Err(anyhow::anyhow!(
+            "Forcing a version is not supported with independent crate versions"
+        )
+        .into());
+    }
+
+    let mut plans = Vec::new();
+    for workspace_crate in &publishable_crates {
+        eprintln!("Planning release for crate: {}", workspace_crate.name);
+
+        let tag_prefix = config.release_tag_prefix(Some(&workspace_crate.name));
+        let force_tag = chosen_tag(&tag_prefix, Some(workspace_crate))?;
+        let context = CliffContext::load(
+            &dir,
+            &config.cliff_config,
+            &tag_prefix,
+            &force_tag,
+            false,
+            Some(workspace_crate),
+        )?;
+        if context.next_version_tag().is_none() {
+            eprintln!(
+                "No unreleased changes for {}, skipping.",
+                workspace_crate.name
+            );
+            continue;
+        }
+
+        let mut plan = ReleasePlan::from_context(
+            &repository,
+            &context,
+            &tag_prefix,
+            Some(workspace_crate),
+            vec![workspace_crate.name.clone()],
+            force_tag.is_some(),
+            skip_semver_checks,
+        )?;
+        if let Some(reason) = chosen_reason {
+            plan.bump_reason = reason;
+        }
+        check_branch_policy(&repository, &config, &plan.next_version)?;
+        plans.push(plan);
+    }
+
+    if plans.is_empty() {
+        return Err(anyhow::anyhow!("No crates have unreleased changes").into());
+    }
+
+    Ok(plans)
+}
+
+/// Render a changelog for each crate that has changes in the next workspace release, using only the
+/// commits that touch the crate's directory.
+///
+/// A crate in the root of the workspace is left out, because its changes are in the root changelog.
+fn plan_crate_changelogs(
+    dir: impl AsRef<Path>,
+    config: &ReleaseConfig,
+    next_tag: &str,
+    crates: &[WorkspaceCrate],
+) -> anyhow::Result<Vec<CrateChangelog>> {
+    let tag_prefix = config.release_tag_prefix(None);
+    let next_tag = Some(next_tag.to_string());
+
+    let mut changelogs = Vec::new();
+    for workspace_crate in crates.iter().filter(|c| !c.path.as_os_str().is_empty()) {
+        let context = CliffContext::load(
+            &dir,
+            &config.cliff_config,
+            &tag_prefix,
+            &next_tag,
+            false,
+            Some(workspace_crate),
+        )?;
+        if context.unreleased_commits().is_empty() {
+            eprintln!(
+                "No changes to {} in this release, its changelog is unchanged.",
+                workspace_crate.name
+            );
+            continue;
+        }
+
+        changelogs.push(CrateChangelog {
+            crate_name: workspace_crate.name.clone(),
+            path: context.changelog_path().to_path_buf(),
+            changelog: context.render_changelog()?,
+        });
+    }
+
+    Ok(changelogs)
+}
+
+/// Applies release plans from [`plan_release`] to the repository.
+///
+/// - Runs semver checks for every plan that has a baseline, before anything is changed.
+/// - Writes the changelog for each plan, and for each crate that has its own changelog. A crate's
+///   changelog is added to the `include` list of its manifest if it would not be packaged.
+/// - Sets the version in the `Cargo.toml` files to the planned version.
+pub fn execute_release_plan(
+    dir: impl AsRef<Path>,
+    plans: &[ReleasePlan],
+    i_am_so_sorry_but_my_features_clash: bool,
+) -> Result<(), ReleaseError> {
     // Ensure the changes on the current branch pass semver checks.
-    if skip_semver_checks {
-        let msg = "Semver checks were skipped for this release. Ensure the version bump is intentional.";
-        if std::env::var("GITHUB_ACTIONS").as_deref() == Ok("true") {
-            println!("::warning title=Semver Checks Skipped::{msg}");
-        } else {
-            eprintln!("WARNING: {msg}");
+    for plan in plans {
+        if let Some(baseline) = &plan.semver_baseline {
+            println!(
+                "Running semver checks against {} as a {} release",
+                baseline.tag,
+                baseline.release_type.as_str()
+            );
+            run_semver_checks(
+                &dir,
+                plan.crate_name.as_deref(),
+                &baseline.revision,
+                baseline.release_type,
+                i_am_so_sorry_but_my_features_clash,
+            )?;
         }
-    } else {
-        match get_released_version_tag(&dir, &cliff_config, &force_tag) {
-            Ok(released_version_tag) => {
-                println!("Retrieving revision for tag: {}", released_version_tag);
-                let revision = get_revision_for_tag(&repository, &released_version_tag)?;
-                run_semver_checks(&dir, &revision, i_am_so_sorry_but_my_features_clash)?;
-            }
-            Err(e) => {
-                eprintln!("No previous release found, skipping semver checks: {e:?}");
-            }
+    }
+
+    for plan in plans {
+        write_changelog(&plan.changelog_path, &plan.changelog)?;
+        if let Some(crate_name) = &plan.crate_name {
+            include_in_package(&dir, crate_name, "CHANGELOG.md")?;
+        }
+        for crate_changelog in &plan.crate_changelogs {
+            write_changelog(&crate_changelog.path, &crate_changelog.changelog)?;
+            include_in_package(&dir, &crate_changelog.crate_name, "CHANGELOG.md")?;
+        }
+
+        // Set the version in the Cargo.toml files.
+        match &plan.crate_name {
+            Some(crate_name) => set_independent_version(&dir, crate_name, &plan.next_version)?,
+            None => set_version(&dir, &plan.next_version)?,
         }
     }
 
     Ok(())
 }
 
+/// Check that publishable crates inherit their `version`, `edition`, `license` and `repository`
+/// from `[workspace.package]`.
+///
+/// Only the fields that `[workspace.package]` defines are checked. The `version` is not checked
+/// when crates are versioned independently, by the [`ReleaseConfig`]. Fails if any crate does not
+/// inherit a field, unless `fix` is set, in which case the fields are rewritten to inherit from the
+/// workspace.
+pub fn check_workspace(dir: impl AsRef<Path>, fix: bool) -> Result<(), ReleaseError> {
+    let config = ReleaseConfig::load(&dir)?;
+    let issues = check_inheritance(&dir, config.independent_versions, fix)?;
+
+    if issues.is_empty() {
+        println!("All publishable crates inherit from the workspace.");
+        return Ok(());
+    }
+
+    for issue in &issues {
+        if fix {
+            println!("Fixed {issue}");
+        } else {
+            println!("{issue}");
+        }
+    }
+    if !fix {
+        return Err(ReleaseError::Config {
+            message: format!(
+                "Publishable crates do not inherit {} field(s) from the workspace, run `check-workspace --fix` to fix them",
+                issues.len()
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that the tools, environment and configuration that a release needs are available.
+///
+/// Prints a table of the checks and how to fix any that failed. Fails if any check failed.
+pub fn doctor(dir: impl AsRef<Path>) -> Result<(), ReleaseError> {
+    let checks = doctor::run_checks(dir);
+    doctor::print_checks(&checks);
+
+    let failed = checks.iter().filter(|c| !c.passed).count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{failed} of {} checks failed", checks.len()).into());
+    }
+
+    println!("All checks passed.");
+
+    Ok(())
+}
+
 /// Publishes a release if one is found.
 ///
 /// - First checks whether the current HEAD commit is part of a releasable change. A change is
-///   releasable if the commit was introduced by a PR that has the `hra-release` label.
-/// - If a releasable change is found, it tags the current HEAD commit with the version from the
-///   `Cargo.toml` file.
-/// - Finally, it publishes the crates.
+///   releasable if the commit was introduced by a PR that has the release label, which is
+///   `hra-release` unless the [`ReleaseConfig`] sets another. Otherwise, it stops and returns
+///   [`PublishOutcome::NotReleasable`], which is not an error.
+/// - If a releasable change is found, it checks that every publishable crate and the top entry of
+///   `CHANGELOG.md` are at the version from the root `Cargo.toml` file, then tags the current HEAD
+///   commit with that version.
+/// - Then it publishes the crates, skipping any that are already in their registry. If no crate
+///   was published, the tag is deleted again. Otherwise, it is kept and a recovery report is
+///   printed.
+/// - Finally, it creates a release on the `forge` for the new tag.
+///
+/// A release tag that already exists locally or on the `remote` is left alone if it points to the
+/// current HEAD commit. If it points to another commit, publishing fails, unless
+/// [`PublishOptions::allow_retag`] is set and none of the released crates are in their registry.
+///
+/// With independent versions, each publishable crate is tagged with its own `<crate>-vX.Y.Z`
+/// tag. Crates whose tag is on an earlier commit, and that are already in their registry at that
+/// version, are unchanged and are skipped. Any other existing tag is checked as above. This
+/// is enabled by the [`ReleaseConfig`], or by the [`ConfigOverrides`].
+///
+/// With `dry_run`, every check is run but nothing is tagged, pushed, published or released.
+/// Instead, the actions that would have been taken are printed.
 pub fn publish_release(
     dir: impl AsRef<Path>,
-    git_token: String,
-    danger_skip_releasable_changes_check: bool,
-    danger_skip_create_gh_release: bool,
-) -> anyhow::Result<()> {
+    forge: &dyn Forge,
+    remote: GitRemote,
+    overrides: ConfigOverrides,
+    options: PublishOptions,
+) -> Result<PublishOutcome, ReleaseError> {
+    let PublishOptions {
+        danger_skip_releasable_changes_check,
+        danger_skip_create_release,
+        dry_run,
+        allow_retag,
+    } = options;
     let repository = git2::Repository::open(&dir).context("Failed to open git repository")?;
+    let config = ReleaseConfig::load(&dir)?.with_overrides(overrides)?;
 
     if !danger_skip_releasable_changes_check {
-        let maybe_pr_number = is_releasable_change(&repository, &dir)?;
+        let maybe_pr_number = is_releasable_change(&repository, forge, &config.release_label)?;
         let Some(pr_number) = maybe_pr_number else {
-            println!("Not a releasable change, stopping.");
-            return Ok(());
+            println!(
+                "Not a releasable change, the HEAD commit is not from a merged pull request with the '{}' label, stopping.",
+                config.release_label
+            );
+            return Ok(PublishOutcome::NotReleasable);
         };
         println!("Found releasable change with PR number: {}", pr_number);
     }
+    check_artifact_crates(&dir, &config.artifacts)?;
 
-    let cargo_toml =
-        read_to_string(dir.as_ref().join("Cargo.toml")).context("Failed to read Cargo.toml")?;
-    let current_version = get_current_version_from_cargo_toml(&cargo_toml)
-        .context("Failed to find version in Cargo.toml")?;
-    let current_tag = format!("v{current_version}");
+    if config.independent_versions {
+        publish_independent_release(&repository, &dir, &config, forge, &remote, &options)?;
+        return Ok(PublishOutcome::Published);
+    }
 
-    tag(&repository, &current_tag, &current_tag).context("Failed to tag the release")?;
-    println!("Tagged current HEAD with: {}", current_tag);
+    let current_version =
+        get_current_version(&dir).context("Failed to find version in Cargo.toml")?;
+    let current_tag = format!("{}{current_version}", config.release_tag_prefix(None));
+    check_release_versions(&dir, &current_version)
+        .context("The release versions do not match, nothing was tagged")?;
+    check_branch_policy(&repository, &config, &current_version)?;
+    let status = publish_status(&dir)?;
+    let retag = check_existing_tag(
+        &repository,
+        &status,
+        &remote,
+        &current_tag,
+        allow_retag,
+        None,
+    )? == ExistingTag::Move;
+    let notes = release_notes(&dir.as_ref().join("CHANGELOG.md"), &current_version);
+    let mut assets = Vec::new();
+    if !danger_skip_create_release {
+        for (crate_name, bins) in &config.artifacts {
+            assets.extend(build_artifacts(
+                &dir,
+                crate_name,
+                bins,
+                &current_version,
+                dry_run,
+            )?);
+        }
+    }
+
+    if dry_run {
+        report_tag(&repository, &remote, &current_tag)?;
+        publish_dry_run(&dir, &status)?;
+        if !danger_skip_create_release {
+            report_release(
+                forge,
+                &current_tag,
+                &current_version,
+                None,
+                notes.as_deref(),
+                &assets,
+            );
+        }
+
+        println!("Dry run completed successfully, no changes were made.");
+        return Ok(PublishOutcome::Published);
+    }
+
+    let signer = tag_signer(&dir, &config)?;
+    publish_or_roll_back(
+        &repository,
+        &dir,
+        &config,
+        &remote,
+        &status,
+        &[(current_tag.clone(), retag)],
+        signer.as_ref(),
+    )?;
+
+    if !danger_skip_create_release {
+        create_release(forge, &current_tag, &current_version, None, notes, &assets)
+            .context("Failed to create release")?;
+    }
+
+    println!("Release-util completed successfully. Another successful release on the 📔📘!");
+
+    Ok(PublishOutcome::Published)
+}
+
+fn publish_independent_release(
+    repository: &git2::Repository,
+    dir: impl AsRef<Path>,
+    config: &ReleaseConfig,
+    forge: &dyn Forge,
+    remote: &GitRemote,
+    options: &PublishOptions,
+) -> anyhow::Result<()> {
+    let PublishOptions {
+        danger_skip_create_release,
+        dry_run,
+        allow_retag,
+        ..
+    } = *options;
+
+    let signer = if dry_run {
+        None
+    } else {
+        tag_signer(&dir, config)?
+    };
+
+    let status = publish_status(&dir)?;
+    let mut released_tags = Vec::new();
+    for workspace_crate in workspace_crates(&dir)?.iter().filter(|c| c.publish) {
+        let manifest_path = dir.as_ref().join(&workspace_crate.path).join("Cargo.toml");
+        let cargo_toml = read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+        let current_version =
+            get_current_version_from_cargo_toml(&cargo_toml).with_context(|| {
+                format!(
+                    "Crate '{}' must set an explicit version to be versioned independently",
+                    workspace_crate.name
+                )
+            })?;
+        let current_tag = format!(
+            "{}{current_version}",
+            config.release_tag_prefix(Some(&workspace_crate.name))
+        );
+
+        let retag = match check_existing_tag(
+            repository,
+            &status,
+            remote,
+            &current_tag,
+            allow_retag,
+            Some(&workspace_crate.name),
+        )? {
+            ExistingTag::Released => continue,
+            existing => existing == ExistingTag::Move,
+        };
+        check_branch_policy(repository, config, &current_version)?;
+        let assets = match config.artifacts.get(&workspace_crate.name) {
+            Some(bins) if !danger_skip_create_release => {
+                build_artifacts(&dir, &workspace_crate.name, bins, &current_version, dry_run)?
+            }
+            _ => Vec::new(),
+        };
+
+        let notes = release_notes(
+            &dir.as_ref()
+                .join(&workspace_crate.path)
+                .join("CHANGELOG.md"),
+            &current_version,
+        );
+        released_tags.push((
+            workspace_crate.name.clone(),
+            current_version,
+            current_tag,
+            retag,
+            notes,
+            assets,
+        ));
+    }
 
-    push_tag(&repository, &git_token, &current_tag).context("Failed to push tag to remote")?;
-    println!("Pushed tag to remote: {}", current_tag);
+    if released_tags.is_empty() {
+        println!("No crates have new versions to release, stopping.");
+        return Ok(());
+    }
 
-    publish(&dir).context("Failed to publish crates")?;
+    if dry_run {
+        for (_, _, current_tag, _, _, _) in &released_tags {
+            report_tag(repository, remote, current_tag)?;
+        }
+        publish_dry_run(&dir, &status)?;
+        if !danger_skip_create_release {
+            for (crate_name, current_version, current_tag, _, notes, assets) in &released_tags {
+                report_release(
+                    forge,
+                    current_tag,
+                    current_version,
+                    Some(crate_name),
+                    notes.as_deref(),
+                    assets,
+                );
+            }
+        }
 
-    if !danger_skip_create_gh_release {
-        create_gh_release(&dir, &current_tag).context("Failed to create GitHub release")?;
+        println!("Dry run completed successfully, no changes were made.");
+        return Ok(());
+    }
+
+    // Every crate passed its checks, so the tags are created and pushed together, and are rolled
+    // back together if nothing is published.
+    let tags = released_tags
+        .iter()
+        .map(|(_, _, tag, retag, _, _)| (tag.clone(), *retag))
+        .collect::<Vec<_>>();
+    publish_or_roll_back(
+        repository,
+        &dir,
+        config,
+        remote,
+        &status,
+        &tags,
+        signer.as_ref(),
+    )?;
+
+    if !danger_skip_create_release {
+        for (crate_name, current_version, current_tag, _, notes, assets) in released_tags {
+            create_release(
+                forge,
+                &current_tag,
+                &current_version,
+                Some(&crate_name),
+                notes,
+                &assets,
+            )
+            .context("Failed to create release")?;
+        }
     }
 
     println!("Release-util completed successfully. Another successful release on the 📔📘!");
@@ -108,24 +676,95 @@ pub fn publish_release(
     Ok(())
 }
 
+/// Resolve the key that release tags are signed with, if signing is configured.
+fn tag_signer(dir: impl AsRef<Path>, config: &ReleaseConfig) -> anyhow::Result<Option<TagSigner>> {
+    config
+        .signing
+        .as_ref()
+        .map(|signing| TagSigner::from_config(&dir, signing))
+        .transpose()
+        .context("Failed to load the tag signing key")
+}
+
+/// Tag HEAD for a release, and check the signature on the tag when it is signed.
+///
+/// With `retag`, an existing tag on another commit is moved to HEAD.
+fn tag_release(
+    repository: &git2::Repository,
+    current_tag: &str,
+    signer: Option<&TagSigner>,
+    retag: bool,
+) -> anyhow::Result<()> {
+    tag(repository, current_tag, current_tag, signer, retag)
+        .context("Failed to tag the release")?;
+    println!("Tagged current HEAD with: {}", current_tag);
+
+    if let Some(signer) = signer {
+        let signed_by = verify_tag(repository, current_tag, signer.verifier())
+            .context("The signature on the new tag could not be verified")?;
+        println!("Verified the signature on {current_tag}: {signed_by}");
+    }
+
+    Ok(())
+}
+
+/// Verify the signature on a release tag, using the signing settings in the release configuration.
+pub fn verify_release_tag(dir: impl AsRef<Path>, tag: &str) -> Result<(), ReleaseError> {
+    let repository = git2::Repository::open(&dir).context("Failed to open git repository")?;
+    let config = ReleaseConfig::load(&dir)?;
+    let Some(signing) = &config.signing else {
+        return Err(ReleaseError::Signing {
+            message: "Tag signing is not configured, add a `signing` section to the release configuration".to_string(),
+        });
+    };
+
+    let verifier = TagVerifier::from_config(&dir, signing)?;
+    let signed_by = verify_tag(&repository, tag, &verifier)?;
+    println!("Tag '{tag}' has a good signature from {signed_by}");
+
+    Ok(())
+}
+
+/// Check that the configured branch policy permits releasing `version` from the current branch.
+fn check_branch_policy(
+    repository: &git2::Repository,
+    config: &ReleaseConfig,
+    version: &str,
+) -> anyhow::Result<()> {
+    let version = semver::Version::parse(version)
+        .with_context(|| format!("Invalid release version: {version}"))?;
+
+    config
+        .check_branch_policy(repository, &version)
+        .context("The release is not permitted by the branch policy")
+}
+
+/// Turn a version, with or without the `tag_prefix`, into a release tag.
 pub(crate) fn input_version_to_version_tag(
     force_version: Option<String>,
+    tag_prefix: &str,
 ) -> anyhow::Result<Option<String>> {
     let force_tag = match force_version {
         Some(input) if input.is_empty() => None,
-        Some(maybe_version_tag) => match maybe_version_tag.strip_prefix("v") {
+        Some(maybe_version_tag) => match maybe_version_tag.strip_prefix(tag_prefix) {
             Some(version) => {
                 if semver::Version::parse(version).is_ok() {
                     Some(maybe_version_tag)
                 } else {
-                    anyhow::bail!("Invalid version format: {}", version);
+                    anyhow::bail!(ReleaseError::InvalidVersion {
+                        version: version.to_string(),
+                        message: format!("Invalid version format: {version}"),
+                    });
                 }
             }
             None => {
                 if semver::Version::parse(&maybe_version_tag).is_ok() {
-                    Some(format!("v{}", maybe_version_tag))
+                    Some(format!("{}{}", tag_prefix, maybe_version_tag))
                 } else {
-                    anyhow::bail!("Invalid version format: {}", maybe_version_tag);
+                    anyhow::bail!(ReleaseError::InvalidVersion {
+                        message: format!("Invalid version format: {maybe_version_tag}"),
+                        version: maybe_version_tag,
+                    });
                 }
             }
         },
@@ -135,30 +774,475 @@ pub(crate) fn input_version_to_version_tag(
     Ok(force_tag)
 }
 
+/// Create and push the release `tags` on HEAD, then publish the crates in the workspace.
+///
+/// Each tag is paired with whether it is moved from another commit. Every check for the release
+/// must have passed first, so that no tag is pushed for a release that cannot happen.
+///
+/// If tagging or publishing fails before any crate is in its registry, the release did not happen,
+/// so the tags that this run created are deleted locally and from the remote. Otherwise, the tags
+/// are kept and a report is printed that says how to finish the release.
+fn publish_or_roll_back(
+    repository: &git2::Repository,
+    dir: impl AsRef<Path>,
+    config: &ReleaseConfig,
+    remote: &GitRemote,
+    status: &[CrateStatus],
+    tags: &[(String, bool)],
+    signer: Option<&TagSigner>,
+) -> anyhow::Result<()> {
+    let mut tagged = Vec::new();
+    let mut summary = PublishSummary::default();
+    let mut tag_and_publish = || -> anyhow::Result<()> {
+        for (tag, retag) in tags {
+            tagged.push(tag.clone());
+            tag_release(repository, tag, signer, *retag)?;
+            push_tag(repository, remote, tag, *retag).context("Failed to push tag to remote")?;
+            println!("Pushed tag to remote: {tag}");
+        }
+
+        publish(repository, &dir, config, status, &mut summary)
+    };
+    let Err(e) = tag_and_publish() else {
+        return Ok(());
+    };
+
+    if !summary.any_uploaded() {
+        println!("No crates were published, rolling back the release tags.");
+        for tag in &tagged {
+            delete_tag(repository, remote, tag).with_context(|| {
+                format!("Failed to roll back tag '{tag}' after the release failed: {e:#}")
+            })?;
+            println!("Rolled back tag: {}", tag);
+        }
+
+        return Err(e.context("Failed to release the crates, the release tags were rolled back"));
+    }
+
+    println!("Publishing failed after some crates were published.");
+    summary.print();
+    println!("Recovery:");
+    println!("  The release tags were kept: {}", tagged.join(", "));
+    println!("  Fix the problem and run `publish` again on the same commit.");
+    println!("  Crates that are already published will be skipped.");
+
+    Err(e.context("Failed to publish crates, see the recovery report"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::GitCredentials;
 
     #[test]
     fn convert_input_version_to_version_tag() {
         // Maps no input to None
-        assert_eq!(input_version_to_version_tag(None).unwrap(), None);
+        assert_eq!(input_version_to_version_tag(None, "v").unwrap(), None);
 
         // Valid version with 'v' prefix remains unchanged
         assert_eq!(
-            input_version_to_version_tag(Some("v1.2.3".to_string())).unwrap(),
+            input_version_to_version_tag(Some("v1.2.3".to_string()), "v").unwrap(),
             Some("v1.2.3".to_string())
         );
 
         // Valid version gets prefixed with 'v'
         assert_eq!(
-            input_version_to_version_tag(Some("1.2.3".to_string())).unwrap(),
+            input_version_to_version_tag(Some("1.2.3".to_string()), "v").unwrap(),
             Some("v1.2.3".to_string())
         );
 
         // Invalid semver is rejected
-        assert!(input_version_to_version_tag(Some("invalid".to_string())).is_err());
+        let err = input_version_to_version_tag(Some("invalid".to_string()), "v").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ReleaseError>()
+                .map(ReleaseError::exit_code),
+            Some(6)
+        );
         // Invalid semver with a 'v' prefix is rejected
-        assert!(input_version_to_version_tag(Some("vinvalid".to_string())).is_err());
+        assert!(input_version_to_version_tag(Some("vinvalid".to_string()), "v").is_err());
+    }
+
+    fn init_repository(path: &Path, branch: &str) -> git2::Repository {
+        git2::Repository::init_opts(
+            path,
+            git2::RepositoryInitOptions::new().initial_head(branch),
+        )
+        .unwrap()
+    }
+
+    fn commit_all(repository: &git2::Repository, message: &str) {
+        let mut index = repository.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repository.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents = repository
+            .head()
+            .ok()
+            .map(|head| vec![head.peel_to_commit().unwrap()])
+            .unwrap_or_default();
+        let parents = parents.iter().collect::<Vec<_>>();
+        repository
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+    }
+
+    const CLIFF_TOML: &str = r#"
+[changelog]
+body = "{% for commit in commits %}- {{ commit.message }}\n{% endfor %}"
+
+[git]
+conventional_commits = true
+filter_unconventional = false
+commit_parsers = [
+    { message = "^feat", group = "<!-- 00 -->Features" },
+    { message = ".*", group = "<!-- 01 -->Other" },
+]
+"#;
+
+    #[test]
+    fn plan_release_without_changing_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = init_repository(dir.path(), "main");
+        let cargo_toml = "[package]\nname = \"planned\"\nversion = \"0.1.0\"\n";
+        std::fs::write(dir.path().join("Cargo.toml"), cargo_toml).unwrap();
+        std::fs::write(dir.path().join("cliff.toml"), CLIFF_TOML).unwrap();
+        commit_all(&repository, "chore: init");
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        repository
+            .tag_lightweight("v0.1.0", head.as_object(), false)
+            .unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "pub fn f() {}").unwrap();
+        commit_all(&repository, "feat: add f");
+
+        let plans = plan_release(
+            dir.path(),
+            ConfigOverrides::default(),
+            VersionOptions::default(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(plans.len(), 1);
+        let plan = &plans[0];
+        assert_eq!(plan.previous_tag.as_deref(), Some("v0.1.0"));
+        assert_eq!(plan.next_tag, "v0.2.0");
+        assert_eq!(plan.next_version, "0.2.0");
+        assert_eq!(plan.bump_reason, BumpReason::Feature);
+        assert_eq!(plan.crates, vec!["planned".to_string()]);
+        assert_eq!(plan.commits.len(), 1);
+        assert_eq!(plan.commits[0].name, "Features");
+        assert_eq!(plan.commits[0].commits[0].message, "feat: add f");
+        let baseline = plan.semver_baseline.as_ref().unwrap();
+        assert_eq!(baseline.tag, "v0.1.0");
+        assert_eq!(baseline.revision, head.id().to_string());
+        assert_eq!(baseline.release_type, ReleaseType::Major);
+
+        // A requested bump is applied to the previous release instead.
+        let version = VersionOptions {
+            bump: Some(ReleaseType::Patch),
+            ..VersionOptions::default()
+        };
+        let plans = plan_release(dir.path(), ConfigOverrides::default(), version, false).unwrap();
+        assert_eq!(plans[0].next_tag, "v0.1.1");
+        assert_eq!(
+            plans[0].bump_reason,
+            BumpReason::Requested(ReleaseType::Patch)
+        );
+
+        assert!(!dir.path().join("CHANGELOG.md").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(),
+            cargo_toml
+        );
+    }
+
+    #[test]
+    fn plan_the_next_pre_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = init_repository(dir.path(), "main");
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"planned\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("cliff.toml"), CLIFF_TOML).unwrap();
+        commit_all(&repository, "chore: init");
+        let tag_head = |name: &str| {
+            let head = repository.head().unwrap().peel_to_commit().unwrap();
+            repository
+                .tag_lightweight(name, head.as_object(), false)
+                .unwrap();
+        };
+        tag_head("v0.1.0");
+        std::fs::write(dir.path().join("lib.rs"), "pub fn f() {}").unwrap();
+        commit_all(&repository, "feat: add f");
+
+        let plan_pre_release = |channel| {
+            plan_release(
+                dir.path(),
+                ConfigOverrides::default(),
+                VersionOptions {
+                    pre_release: Some(channel),
+                    ..VersionOptions::default()
+                },
+                false,
+            )
+        };
+
+        let plans = plan_pre_release(PreRelease::Dev).unwrap();
+        assert_eq!(plans[0].next_tag, "v0.2.0-dev.0");
+        assert_eq!(plans[0].bump_reason, BumpReason::PreRelease);
+
+        tag_head("v0.2.0-dev.0");
+        std::fs::write(dir.path().join("lib.rs"), "pub fn f() -> u8 { 1 }").unwrap();
+        commit_all(&repository, "fix: return a value");
+
+        let plans = plan_pre_release(PreRelease::Dev).unwrap();
+        assert_eq!(plans[0].next_tag, "v0.2.0-dev.1");
+        assert_eq!(plans[0].previous_tag.as_deref(), Some("v0.2.0-dev.0"));
+
+        let plans = plan_pre_release(PreRelease::Rc).unwrap();
+        assert_eq!(plans[0].next_tag, "v0.2.0-rc.0");
+
+        let err = plan_pre_release(PreRelease::Alpha).unwrap_err();
+        assert!(err.to_string().contains("0.2.0-alpha.0"), "{err}");
+
+        // Forcing a version and picking a pre-release conflict.
+        let version = VersionOptions {
+            force_version: Some("0.3.0".to_string()),
+            allow_version_skip: false,
+            pre_release: Some(PreRelease::Dev),
+            bump: None,
+        };
+        assert!(plan_release(dir.path(), ConfigOverrides::default(), version, false).is_err());
+    }
+
+    #[test]
+    fn plan_crate_changelogs_from_crate_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = init_repository(dir.path(), "main");
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.package]\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(CONFIG_FILE), "crate-changelogs = true\n").unwrap();
+        std::fs::write(dir.path().join("cliff.toml"), CLIFF_TOML).unwrap();
+        for name in ["a", "b"] {
+            std::fs::create_dir_all(dir.path().join("crates").join(name)).unwrap();
+            std::fs::write(
+                dir.path().join("crates").join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion.workspace = true\n"),
+            )
+            .unwrap();
+        }
+        commit_all(&repository, "chore: init");
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        repository
+            .tag_lightweight("v0.1.0", head.as_object(), false)
+            .unwrap();
+        std::fs::write(dir.path().join("crates/a/lib.rs"), "pub fn f() {}").unwrap();
+        commit_all(&repository, "feat: add f to a");
+
+        let plans = plan_release(
+            dir.path(),
+            ConfigOverrides::default(),
+            VersionOptions::default(),
+            false,
+        )
+        .unwrap();
+
+        // Only the crate with changes gets a changelog, and the root changelog is kept.
+        let plan = &plans[0];
+        assert_eq!(plan.changelog_path, dir.path().join("CHANGELOG.md"));
+        assert_eq!(plan.crate_changelogs.len(), 1);
+        let crate_changelog = &plan.crate_changelogs[0];
+        assert_eq!(crate_changelog.crate_name, "a");
+        assert_eq!(
+            crate_changelog.path,
+            dir.path().join("crates/a/CHANGELOG.md")
+        );
+        assert!(
+            crate_changelog.changelog.contains("add f to a"),
+            "{}",
+            crate_changelog.changelog
+        );
+        assert!(!crate_changelog.path.exists());
+    }
+
+    #[test]
+    fn roll_back_tag_when_nothing_was_published() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        let remote = git2::Repository::init_bare(remote_dir.path()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let repository = init_repository(dir.path(), "main");
+        let mut config = repository.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repository
+            .remote("origin", remote_dir.path().to_str().unwrap())
+            .unwrap();
+
+        // The registry is not configured, so `cargo publish` fails before anything is uploaded.
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"unpublished\"\nversion = \"0.1.0\"\npublish = [\"missing\"]\n",
+        )
+        .unwrap();
+        commit_all(&repository, "chore: initial commit");
+
+        let git_remote = GitRemote::new(GitCredentials::None);
+        let status = workspace_crates(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|c| (c, "0.1.0".to_string(), false))
+            .collect::<Vec<_>>();
+        let err = publish_or_roll_back(
+            &repository,
+            dir.path(),
+            &ReleaseConfig::default(),
+            &git_remote,
+            &status,
+            &[("v0.1.0".to_string(), false)],
+            None,
+        )
+        .unwrap_err();
+
+        assert!(format!("{err:#}").contains("rolled back"), "{err:#}");
+        assert!(repository.find_reference("refs/tags/v0.1.0").is_err());
+        assert!(remote.find_reference("refs/tags/v0.1.0").is_err());
+    }
+
+    #[test]
+    fn check_every_crate_before_tagging() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        let remote = git2::Repository::init_bare(remote_dir.path()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let repository = init_repository(dir.path(), "main");
+        let mut config = repository.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repository
+            .remote("origin", remote_dir.path().to_str().unwrap())
+            .unwrap();
+
+        // Neither crate is in the registry at its new version.
+        let index = publish_release::tests::serve_index("{\"name\":\"a\",\"vers\":\"0.1.0\"}\n");
+        std::fs::create_dir_all(dir.path().join(".cargo")).unwrap();
+        std::fs::write(
+            dir.path().join(".cargo/config.toml"),
+            format!("[registries.local]\nindex = \"sparse+{index}\"\n"),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\"]\n",
+        )
+        .unwrap();
+        for name in ["a", "b"] {
+            std::fs::create_dir_all(dir.path().join(name)).unwrap();
+            std::fs::write(
+                dir.path().join(name).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{name}\"\nversion = \"0.2.0\"\npublish = [\"local\"]\n"
+                ),
+            )
+            .unwrap();
+        }
+        commit_all(&repository, "chore: initial commit");
+
+        // The second crate's tag is on an earlier commit, and the crate is not published, so its
+        // check fails after the first crate's checks have passed.
+        tag(&repository, "b-v0.2.0", "b-v0.2.0", None, false).unwrap();
+        std::fs::write(dir.path().join("a/lib.rs"), "pub fn f() {}").unwrap();
+        commit_all(&repository, "feat: add f to a");
+
+        let forge = forge::connect(
+            forge::ForgeKind::GitHub,
+            Some("http://127.0.0.1:9"),
+            "owner/independent",
+            "token",
+        )
+        .unwrap();
+        let err = publish_release(
+            dir.path(),
+            forge.as_ref(),
+            GitRemote::new(GitCredentials::None),
+            ConfigOverrides {
+                independent_versions: true,
+                ..ConfigOverrides::default()
+            },
+            PublishOptions {
+                danger_skip_releasable_changes_check: true,
+                danger_skip_create_release: true,
+                ..PublishOptions::default()
+            },
+        )
+        .unwrap_err();
+
+        assert!(
+            matches!(&err, ReleaseError::TagConflict { tag, .. } if tag == "b-v0.2.0"),
+            "{err:?}"
+        );
+        assert!(repository.find_reference("refs/tags/a-v0.2.0").is_err());
+        assert!(remote.find_reference("refs/tags/a-v0.2.0").is_err());
+    }
+
+    #[test]
+    fn refuse_to_publish_from_a_feature_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = init_repository(dir.path(), "feature");
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"unreleased\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("CHANGELOG.md"),
+            "# Changelog\n\n## \\[0.1.0\\]\n",
+        )
+        .unwrap();
+        commit_all(&repository, "chore: initial commit");
+
+        let forge = forge::connect(
+            forge::ForgeKind::GitHub,
+            Some("http://127.0.0.1:9"),
+            "owner/unreleased",
+            "token",
+        )
+        .unwrap();
+        let err = publish_release(
+            dir.path(),
+            forge.as_ref(),
+            GitRemote::new(GitCredentials::None),
+            ConfigOverrides::default(),
+            PublishOptions {
+                danger_skip_releasable_changes_check: true,
+                danger_skip_create_release: true,
+                dry_run: true,
+                ..PublishOptions::default()
+            },
+        )
+        .unwrap_err();
+
+        assert!(
+            matches!(&err, ReleaseError::BranchPolicy { branch, .. } if branch == "feature"),
+            "{err:?}"
+        );
+        assert_eq!(err.exit_code(), 10);
+        assert!(err.to_string().contains("may not release"), "{err}");
+        assert!(repository.find_reference("refs/tags/v0.1.0").is_err());
     }
 }
@@ -0,0 +1,1086 @@
+//! Reading and rewriting `Cargo.toml` manifests in a workspace.
+//!
+//! Manifests are edited with `toml_edit` so that formatting and comments are preserved.
+
+use anyhow::Context;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table};
+
+/// The dependency tables, in a manifest, that may refer to other crates in the workspace.
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The `[package]` fields that publishable crates must inherit from `[workspace.package]`.
+const INHERITED_FIELDS: [&str; 4] = ["version", "edition", "license", "repository"];
+
+/// A single value that was changed in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    /// The manifest that was changed.
+    pub path: PathBuf,
+    /// The dotted path to the key that was changed, such as `workspace.package.version`.
+    pub key: String,
+    /// The value before the change.
+    pub from: String,
+    /// The value after the change.
+    pub to: String,
+}
+
+impl Display for VersionChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: `{}` {} -> {}",
+            self.path.display(),
+            self.key,
+            self.from,
+            self.to
+        )
+    }
+}
+
+/// A `[package]` field of a publishable crate that does not inherit from `[workspace.package]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InheritanceIssue {
+    /// The manifest of the crate.
+    pub path: PathBuf,
+    /// The field, such as `version`.
+    pub field: String,
+    /// The value that the crate sets instead, or `None` if the field is missing.
+    pub value: Option<String>,
+}
+
+impl Display for InheritanceIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Some(value) => write!(
+                f,
+                "{}: `package.{}` is set to {value}, instead of `{}.workspace = true`",
+                self.path.display(),
+                self.field,
+                self.field
+            ),
+            None => write!(
+                f,
+                "{}: `package.{}` is missing, instead of `{}.workspace = true`",
+                self.path.display(),
+                self.field,
+                self.field
+            ),
+        }
+    }
+}
+
+/// A parsed manifest, along with where it was read from.
+pub(crate) struct Manifest {
+    pub(crate) path: PathBuf,
+    pub(crate) document: DocumentMut,
+}
+
+impl Manifest {
+    /// Read and parse the manifest at the given path.
+    pub(crate) fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let document = content
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Invalid TOML in {}", path.display()))?;
+
+        Ok(Self { path, document })
+    }
+
+    /// Write the manifest back to where it was read from.
+    pub(crate) fn write(&self) -> anyhow::Result<()> {
+        std::fs::write(&self.path, self.document.to_string())
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// The name of the package defined by this manifest, if it defines one.
+    pub(crate) fn package_name(&self) -> Option<&str> {
+        self.document
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str())
+    }
+}
+
+/// Read the root manifest in `dir` and the manifests of all the workspace members.
+///
+/// The root manifest is always the first in the returned list. If the root manifest does not
+/// define a workspace, it is the only manifest returned.
+pub(crate) fn read_workspace(dir: impl AsRef<Path>) -> anyhow::Result<Vec<Manifest>> {
+    let dir = dir.as_ref();
+    let root = Manifest::read(dir.join("Cargo.toml"))?;
+
+    let mut member_dirs = Vec::new();
+    if let Some(workspace) = root.document.get("workspace") {
+        let patterns = |key: &str| -> Vec<String> {
+            workspace
+                .get(key)
+                .and_then(|v| v.as_array())
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|v| v.as_str().map(ToString::to_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let excluded = patterns("exclude")
+            .iter()
+            .map(|p| dir.join(p))
+            .collect::<HashSet<_>>();
+
+        for pattern in patterns("members") {
+            let pattern = dir.join(&pattern);
+            let matches = glob::glob(&pattern.to_string_lossy())
+                .with_context(|| format!("Invalid workspace member pattern: {pattern:?}"))?;
+            for member in matches {
+                let member = member.context("Failed to read workspace member")?;
+                if member.join("Cargo.toml").is_file()
+                    && !excluded.contains(&member)
+                    && member != dir
+                {
+                    member_dirs.push(member);
+                }
+            }
+        }
+    }
+
+    let mut manifests = vec![root];
+    for member_dir in member_dirs {
+        manifests.push(Manifest::read(member_dir.join("Cargo.toml"))?);
+    }
+
+    Ok(manifests)
+}
+
+/// A package that is a member of the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WorkspaceCrate {
+    pub(crate) name: String,
+    /// The directory containing the crate, relative to the workspace root.
+    pub(crate) path: PathBuf,
+    /// The version of the crate, whether it is set explicitly or inherited from the workspace.
+    pub(crate) version: Option<String>,
+    /// Whether the crate may be published to a registry.
+    pub(crate) publish: bool,
+    /// The registry that the crate is published to, if it is restricted to a named registry.
+    ///
+    /// When there are several, the first is used, as `cargo publish` would require a choice.
+    pub(crate) registry: Option<String>,
+    /// The other workspace crates that this crate needs to be published first.
+    ///
+    /// Dev-dependencies are not included, because they are not needed to publish the crate.
+    pub(crate) dependencies: Vec<String>,
+}
+
+/// List the packages in the workspace in `dir`, ordered so that every crate comes after the
+/// workspace crates that it depends on.
+pub(crate) fn workspace_crates(dir: impl AsRef<Path>) -> anyhow::Result<Vec<WorkspaceCrate>> {
+    let dir = dir.as_ref();
+    let manifests = read_workspace(dir)?;
+
+    let names = manifests
+        .iter()
+        .filter_map(|m| m.package_name().map(ToString::to_string))
+        .collect::<HashSet<_>>();
+    let workspace_dependencies = manifests[0]
+        .document
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"));
+    let workspace_version = manifests[0]
+        .document
+        .get("workspace")
+        .and_then(|w| w.get("package"))
+        .and_then(|p| p.get("version"))
+        .and_then(|v| v.as_str());
+
+    let mut crates = Vec::new();
+    for manifest in &manifests {
+        let Some(name) = manifest.package_name() else {
+            continue;
+        };
+        let package = &manifest.document["package"];
+
+        let publish = is_publishable(package);
+        let registry = package
+            .get("publish")
+            .and_then(|p| p.as_array())
+            .and_then(|a| a.get(0))
+            .and_then(|r| r.as_str())
+            .map(ToString::to_string);
+
+        let version = match package.get("version") {
+            Some(version) if version.is_str() => version.as_str(),
+            Some(_) => workspace_version,
+            None => None,
+        }
+        .map(ToString::to_string);
+
+        let mut tables = vec![
+            manifest.document.get("dependencies"),
+            manifest.document.get("build-dependencies"),
+        ];
+        if let Some(targets) = manifest.document.get("target").and_then(Item::as_table) {
+            for (_, target) in targets.iter() {
+                tables.push(target.get("dependencies"));
+                tables.push(target.get("build-dependencies"));
+            }
+        }
+
+        let mut dependencies = Vec::new();
+        for table in tables.into_iter().flatten() {
+            let Some(table) = table.as_table_like() else {
+                continue;
+            };
+            for (key, dependency) in table.iter() {
+                let inherited = dependency
+                    .get("workspace")
+                    .and_then(|w| w.as_bool())
+                    .unwrap_or(false);
+                let source = if inherited {
+                    workspace_dependencies.and_then(|d| d.get(key))
+                } else {
+                    Some(dependency)
+                };
+                let crate_name = source
+                    .and_then(|s| s.get("package"))
+                    .and_then(|p| p.as_str())
+                    .unwrap_or(key);
+                if names.contains(crate_name)
+                    && crate_name != name
+                    && !dependencies.iter().any(|d| d == crate_name)
+                {
+                    dependencies.push(crate_name.to_string());
+                }
+            }
+        }
+
+        let path = manifest
+            .path
+            .parent()
+            .and_then(|p| p.strip_prefix(dir).ok())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        crates.push(WorkspaceCrate {
+            name: name.to_string(),
+            path,
+            version,
+            publish,
+            registry,
+            dependencies,
+        });
+    }
+
+    // Repeatedly take the crates whose dependencies have all been taken already.
+    let mut ordered: Vec<WorkspaceCrate> = Vec::with_capacity(crates.len());
+    while !crates.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = crates.into_iter().partition(|c| {
+            c.dependencies
+                .iter()
+                .all(|d| ordered.iter().any(|o| &o.name == d))
+        });
+        if ready.is_empty() {
+            anyhow::bail!(
+                "Dependency cycle between workspace crates: {}",
+                waiting
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        ordered.extend(ready);
+        crates = waiting;
+    }
+
+    Ok(ordered)
+}
+
+/// Whether a `[package]` table allows the crate to be published.
+///
+/// Publishing is disabled by either `publish = false` or `publish = []`.
+fn is_publishable(package: &Item) -> bool {
+    match package.get("publish") {
+        Some(publish) => {
+            publish.as_bool().unwrap_or(true) && publish.as_array().is_none_or(|a| !a.is_empty())
+        }
+        None => true,
+    }
+}
+
+/// Make sure that a file in a crate's directory is packaged with the crate.
+///
+/// Files are packaged by default, but not when the manifest has an `include` list that does not
+/// cover the file. The file is then added to the list. Warns if the file is excluded by `exclude`.
+pub(crate) fn include_in_package(
+    dir: impl AsRef<Path>,
+    crate_name: &str,
+    file: &str,
+) -> anyhow::Result<()> {
+    let mut manifests = read_workspace(dir)?;
+    let manifest = manifests
+        .iter_mut()
+        .find(|m| m.package_name() == Some(crate_name))
+        .with_context(|| format!("Crate '{crate_name}' is not a member of the workspace"))?;
+
+    let matches = |patterns: Option<&toml_edit::Array>| {
+        patterns.is_some_and(|patterns| {
+            patterns.iter().filter_map(|p| p.as_str()).any(|pattern| {
+                glob::Pattern::new(pattern.trim_start_matches('/'))
+                    .is_ok_and(|pattern| pattern.matches(file))
+            })
+        })
+    };
+
+    let Some(package) = manifest
+        .document
+        .get_mut("package")
+        .and_then(Item::as_table_like_mut)
+    else {
+        return Ok(());
+    };
+    if matches(package.get("exclude").and_then(Item::as_array)) {
+        eprintln!(
+            "WARNING: {file} is excluded from the {crate_name} package by `exclude` in {}",
+            manifest.path.display()
+        );
+    }
+
+    let Some(include) = package.get_mut("include").and_then(Item::as_array_mut) else {
+        return Ok(());
+    };
+    if matches(Some(include)) {
+        return Ok(());
+    }
+
+    include.push(file);
+    manifest.write()?;
+    println!(
+        "Added {file} to `package.include` in {}",
+        manifest.path.display()
+    );
+
+    Ok(())
+}
+
+/// Find the fields of publishable crates in the workspace in `dir` that should inherit from
+/// `[workspace.package]`, but do not.
+///
+/// Only the [`INHERITED_FIELDS`] that `[workspace.package]` defines are checked, and the `version`
+/// is not checked when crates are versioned independently. With `fix`, each field is rewritten as
+/// `<field>.workspace = true` and the manifests are written back.
+pub(crate) fn check_inheritance(
+    dir: impl AsRef<Path>,
+    independent_versions: bool,
+    fix: bool,
+) -> anyhow::Result<Vec<InheritanceIssue>> {
+    let mut manifests = read_workspace(dir)?;
+
+    let workspace_fields = manifests[0]
+        .document
+        .get("workspace")
+        .and_then(|w| w.get("package"))
+        .map(|package| {
+            INHERITED_FIELDS
+                .into_iter()
+                .filter(|field| package.get(field).is_some())
+                .filter(|field| !(independent_versions && *field == "version"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut issues = Vec::new();
+    for manifest in &mut manifests {
+        let mut manifest_issues = Vec::new();
+        let Some(package) = manifest
+            .document
+            .get_mut("package")
+            .filter(|package| is_publishable(package))
+            .and_then(Item::as_table_like_mut)
+        else {
+            continue;
+        };
+
+        for field in &workspace_fields {
+            let value = match package.get(field) {
+                Some(item) if item.get("workspace").and_then(Item::as_bool) == Some(true) => {
+                    continue;
+                }
+                Some(item) => Some(match item.as_value() {
+                    Some(value) => {
+                        let mut value = value.clone();
+                        value.decor_mut().clear();
+                        value.to_string()
+                    }
+                    None => item.to_string().trim().to_string(),
+                }),
+                None => None,
+            };
+
+            if fix {
+                // Keep the formatting of the value, including any comment after it.
+                let mut workspace = toml_edit::Value::from(true);
+                if let Some(existing) = package.get(field).and_then(Item::as_value) {
+                    *workspace.decor_mut() = existing.decor().clone();
+                }
+                let mut inherit = toml_edit::InlineTable::new();
+                inherit.insert("workspace", workspace);
+                inherit.set_dotted(true);
+                package.insert(field, Item::Value(inherit.into()));
+            }
+
+            manifest_issues.push(InheritanceIssue {
+                path: manifest.path.clone(),
+                field: field.to_string(),
+                value,
+            });
+        }
+
+        if fix && !manifest_issues.is_empty() {
+            manifest.write()?;
+        }
+        issues.extend(manifest_issues);
+    }
+
+    Ok(issues)
+}
+
+/// Set the version of every crate in the workspace in `dir`, and of the references between them.
+///
+/// This updates:
+/// - `[workspace.package].version` in the root manifest.
+/// - `[package].version` in any manifest that specifies it explicitly, rather than inheriting it.
+/// - The `version` of entries in `[workspace.dependencies]` that refer to workspace crates.
+/// - The `version` of entries in member dependency tables that refer to workspace crates.
+///
+/// Returns every change that was made. Manifests that did not need changing are not rewritten.
+pub(crate) fn set_workspace_version(
+    dir: impl AsRef<Path>,
+    version: &semver::Version,
+) -> anyhow::Result<Vec<VersionChange>> {
+    rewrite_versions(dir, version, None)
+}
+
+/// Set the version of a single crate in the workspace in `dir`, and of the references to it.
+///
+/// The crate must specify its version explicitly, rather than inheriting it from the workspace.
+/// The `[workspace.package].version` is left alone.
+pub(crate) fn set_crate_version(
+    dir: impl AsRef<Path>,
+    crate_name: &str,
+    version: &semver::Version,
+) -> anyhow::Result<Vec<VersionChange>> {
+    let manifests = read_workspace(&dir)?;
+    let manifest = manifests
+        .iter()
+        .find(|m| m.package_name() == Some(crate_name))
+        .with_context(|| format!("Crate '{crate_name}' is not a member of the workspace"))?;
+    let explicit = manifest
+        .document
+        .get("package")
+        .and_then(|p| p.get("version"))
+        .is_some_and(|v| v.is_str());
+    if !explicit {
+        anyhow::bail!(
+            "Crate '{crate_name}' must set an explicit `package.version` to be versioned independently, in {}",
+            manifest.path.display()
+        );
+    }
+
+    rewrite_versions(dir, version, Some(crate_name))
+}
+
+/// Rewrite versions in the workspace in `dir`.
+///
+/// When `only_crate` is provided, only that crate's version and the references to it are changed.
+/// Otherwise, every crate in the workspace is given the new version.
+fn rewrite_versions(
+    dir: impl AsRef<Path>,
+    version: &semver::Version,
+    only_crate: Option<&str>,
+) -> anyhow::Result<Vec<VersionChange>> {
+    let mut manifests = read_workspace(dir)?;
+
+    let workspace_crates = manifests
+        .iter()
+        .filter_map(|m| m.package_name().map(ToString::to_string))
+        .filter(|name| only_crate.is_none_or(|only| only == name))
+        .collect::<HashSet<_>>();
+
+    let version = version.to_string();
+    let mut changes = Vec::new();
+    for manifest in &mut manifests {
+        let mut manifest_changes = Vec::new();
+        let is_target_crate = manifest
+            .package_name()
+            .is_some_and(|name| workspace_crates.contains(name));
+        let document = manifest.document.as_table_mut();
+
+        if let Some(workspace) = document
+            .get_mut("workspace")
+            .and_then(Item::as_table_like_mut)
+        {
+            if only_crate.is_none()
+                && let Some(package) = workspace
+                    .get_mut("package")
+                    .and_then(Item::as_table_like_mut)
+            {
+                set_string(
+                    package.get_mut("version"),
+                    "workspace.package.version",
+                    &version,
+                    &mut manifest_changes,
+                );
+            }
+
+            if let Some(dependencies) = workspace
+                .get_mut("dependencies")
+                .and_then(Item::as_table_mut)
+            {
+                set_dependency_versions(
+                    dependencies,
+                    "workspace.dependencies",
+                    &workspace_crates,
+                    &version,
+                    &mut manifest_changes,
+                )?;
+            }
+        }
+
+        if is_target_crate
+            && let Some(package) = document
+                .get_mut("package")
+                .and_then(Item::as_table_like_mut)
+        {
+            set_string(
+                package.get_mut("version"),
+                "package.version",
+                &version,
+                &mut manifest_changes,
+            );
+        }
+
+        for table_name in DEPENDENCY_TABLES {
+            if let Some(dependencies) = document.get_mut(table_name).and_then(Item::as_table_mut) {
+                set_dependency_versions(
+                    dependencies,
+                    table_name,
+                    &workspace_crates,
+                    &version,
+                    &mut manifest_changes,
+                )?;
+            }
+        }
+
+        if let Some(targets) = document.get_mut("target").and_then(Item::as_table_mut) {
+            for (target, target_table) in targets.iter_mut() {
+                let Some(target_table) = target_table.as_table_mut() else {
+                    continue;
+                };
+                for table_name in DEPENDENCY_TABLES {
+                    if let Some(dependencies) = target_table
+                        .get_mut(table_name)
+                        .and_then(Item::as_table_mut)
+                    {
+                        set_dependency_versions(
+                            dependencies,
+                            &format!("target.{target}.{table_name}"),
+                            &workspace_crates,
+                            &version,
+                            &mut manifest_changes,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if !manifest_changes.is_empty() {
+            manifest.write()?;
+            changes.extend(
+                manifest_changes
+                    .into_iter()
+                    .map(|(key, from, to)| VersionChange {
+                        path: manifest.path.clone(),
+                        key,
+                        from,
+                        to,
+                    }),
+            );
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Update the version requirement of every dependency in the table that refers to a workspace
+/// crate.
+///
+/// Dependencies that do not specify a version, or that inherit from the workspace, are left alone.
+/// Fails if a requirement has more than one comparator, such as `>=0.1, <0.3`, because there is no
+/// single operator to keep.
+fn set_dependency_versions(
+    dependencies: &mut Table,
+    table_key: &str,
+    workspace_crates: &HashSet<String>,
+    version: &str,
+    changes: &mut Vec<(String, String, String)>,
+) -> anyhow::Result<()> {
+    for (name, dependency) in dependencies.iter_mut() {
+        // A plain string is only a version requirement, but it can still refer to a workspace
+        // crate that is resolved from the registry.
+        let (crate_name, key, item) = if dependency.is_str() {
+            (
+                name.get().to_string(),
+                format!("{table_key}.{}", name.get()),
+                Some(dependency),
+            )
+        } else if let Some(dependency) = dependency.as_table_like_mut() {
+            let crate_name = dependency
+                .get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(name.get())
+                .to_string();
+            (
+                crate_name,
+                format!("{table_key}.{}.version", name.get()),
+                dependency.get_mut("version"),
+            )
+        } else {
+            continue;
+        };
+        if !workspace_crates.contains(&crate_name) {
+            continue;
+        }
+
+        let Some(existing) = item.as_deref().and_then(Item::as_str) else {
+            continue;
+        };
+        let Some(requirement) = bump_requirement(existing, version)
+            .with_context(|| format!("Cannot update the version requirement at {key}"))?
+        else {
+            continue;
+        };
+
+        set_string(item, &key, &requirement, changes);
+    }
+
+    Ok(())
+}
+
+/// Move a version requirement to `version`, keeping its comparison operator, such as `=` or `^`.
+///
+/// Returns `None` for a requirement that matches every version, such as `*`.
+fn bump_requirement(existing: &str, version: &str) -> anyhow::Result<Option<String>> {
+    let requirement = semver::VersionReq::parse(existing)
+        .with_context(|| format!("Invalid version requirement: {existing}"))?;
+    match requirement.comparators.len() {
+        0 => return Ok(None),
+        1 => {}
+        _ => anyhow::bail!(
+            "The requirement `{existing}` has more than one comparator, set it to match {version} by hand"
+        ),
+    }
+
+    let existing = existing.trim();
+    let operator = &existing[..existing.len()
+        - existing
+            .trim_start_matches(['=', '^', '~', '<', '>', ' '])
+            .len()];
+
+    Ok(Some(format!("{}{version}", operator.trim_end())))
+}
+
+/// Set a string value in place, keeping its surrounding formatting, and record the change.
+///
+/// Does nothing if the item does not exist, is not a string, or already has the desired value.
+fn set_string(
+    item: Option<&mut Item>,
+    key: &str,
+    value: &str,
+    changes: &mut Vec<(String, String, String)>,
+) {
+    let Some(item) = item else {
+        return;
+    };
+    let Some(current) = item.as_str().map(ToString::to_string) else {
+        return;
+    };
+    if current == value {
+        return;
+    }
+
+    let Some(existing) = item.as_value_mut() else {
+        return;
+    };
+    let decor = existing.decor().clone();
+    *existing = toml_edit::Value::from(value);
+    *existing.decor_mut() = decor;
+
+    changes.push((key.to_string(), current, value.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_and_fix_inheritance() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            r#"[workspace]
+members = ["crates/*"]
+
+[workspace.package]
+version = "0.2.0"
+edition = "2024"
+license = "Apache-2.0"
+"#,
+        )
+        .unwrap();
+        for (name, manifest) in [
+            (
+                "a",
+                "[package]\nname = \"a\"\nversion = \"0.1.0\" # drifted\nedition.workspace = true\n\n[dependencies]\n",
+            ),
+            (
+                "b",
+                "[package]\nname = \"b\"\nversion.workspace = true\nedition.workspace = true\nlicense.workspace = true\n",
+            ),
+            (
+                "private",
+                "[package]\nname = \"private\"\nversion = \"0.0.1\"\npublish = false\n",
+            ),
+        ] {
+            std::fs::create_dir_all(dir.path().join("crates").join(name)).unwrap();
+            std::fs::write(
+                dir.path().join("crates").join(name).join("Cargo.toml"),
+                manifest,
+            )
+            .unwrap();
+        }
+
+        let issues = check_inheritance(dir.path(), false, false).unwrap();
+        let found = issues
+            .iter()
+            .map(|i| (i.field.as_str(), i.value.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![("version", Some("\"0.1.0\"")), ("license", None)]
+        );
+
+        // The version is not inherited when crates are versioned independently.
+        assert_eq!(check_inheritance(dir.path(), true, false).unwrap().len(), 1);
+
+        let fixed = check_inheritance(dir.path(), false, true).unwrap();
+        assert_eq!(fixed, issues);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("crates/a/Cargo.toml")).unwrap(),
+            "[package]\nname = \"a\"\nversion.workspace = true # drifted\nedition.workspace = true\nlicense.workspace = true\n\n[dependencies]\n"
+        );
+        assert!(
+            check_inheritance(dir.path(), false, false)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn set_version_in_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            r#"[workspace]
+members = ["crates/*"]
+
+[workspace.package]
+version = "0.1.0" # the release version
+edition = "2024"
+
+[workspace.dependencies]
+# internal crates
+a = { version = "0.1.0", path = "crates/a" }
+b = { version = "=0.1.0", path = "crates/b" }
+serde = "1.0"
+"#,
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.path().join("crates/a")).unwrap();
+        std::fs::write(
+            dir.path().join("crates/a/Cargo.toml"),
+            r#"[package]
+name = "a"
+version.workspace = true
+
+[dependencies]
+b.workspace = true
+"#,
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.path().join("crates/b")).unwrap();
+        std::fs::write(
+            dir.path().join("crates/b/Cargo.toml"),
+            r#"[package]
+name = "b"
+version = "0.1.0"
+
+[dev-dependencies]
+a = { path = "../a", version = "0.1.0" }
+
+[build-dependencies]
+a = "~0.1.0"
+"#,
+        )
+        .unwrap();
+
+        let changes = set_workspace_version(dir.path(), &semver::Version::new(0, 2, 0)).unwrap();
+
+        let keys = changes
+            .iter()
+            .map(|c| {
+                (
+                    c.path.strip_prefix(dir.path()).unwrap().to_path_buf(),
+                    c.key.as_str(),
+                    c.to.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                (
+                    PathBuf::from("Cargo.toml"),
+                    "workspace.package.version",
+                    "0.2.0"
+                ),
+                (
+                    PathBuf::from("Cargo.toml"),
+                    "workspace.dependencies.a.version",
+                    "0.2.0"
+                ),
+                (
+                    PathBuf::from("Cargo.toml"),
+                    "workspace.dependencies.b.version",
+                    "=0.2.0"
+                ),
+                (
+                    PathBuf::from("crates/b/Cargo.toml"),
+                    "package.version",
+                    "0.2.0"
+                ),
+                (
+                    PathBuf::from("crates/b/Cargo.toml"),
+                    "dev-dependencies.a.version",
+                    "0.2.0"
+                ),
+                (
+                    PathBuf::from("crates/b/Cargo.toml"),
+                    "build-dependencies.a",
+                    "~0.2.0"
+                ),
+            ]
+        );
+
+        let root = std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert!(root.contains("version = \"0.2.0\" # the release version"));
+        assert!(root.contains("# internal crates"));
+        assert!(root.contains("serde = \"1.0\""));
+
+        let a = std::fs::read_to_string(dir.path().join("crates/a/Cargo.toml")).unwrap();
+        assert!(a.contains("version.workspace = true"));
+    }
+
+    #[test]
+    fn bump_version_requirements() {
+        assert_eq!(
+            bump_requirement("0.1", "0.2.0").unwrap().as_deref(),
+            Some("0.2.0")
+        );
+        assert_eq!(
+            bump_requirement("= 0.1.0", "0.2.0").unwrap().as_deref(),
+            Some("=0.2.0")
+        );
+        assert_eq!(bump_requirement("*", "0.2.0").unwrap(), None);
+        assert!(bump_requirement(">=0.1, <0.3", "0.2.0").is_err());
+        assert!(bump_requirement("not a requirement", "0.2.0").is_err());
+    }
+
+    #[test]
+    fn list_workspace_crates_in_dependency_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            r#"[workspace]
+members = ["crates/*"]
+exclude = ["crates/excluded"]
+
+[workspace.package]
+version = "0.1.0"
+
+[workspace.dependencies]
+renamed = { package = "base", path = "crates/base", version = "0.1.0" }
+"#,
+        )
+        .unwrap();
+        for (name, manifest) in [
+            (
+                "app",
+                "[package]\nname = \"app\"\nversion = \"0.3.0\"\n\n[dependencies]\nmiddle = { path = \"../middle\", version = \"0.2.0\" }\n",
+            ),
+            (
+                "middle",
+                "[package]\nname = \"middle\"\nversion = \"0.2.0\"\n\n[dependencies]\nrenamed.workspace = true\n\n[dev-dependencies]\napp = { path = \"../app\" }\n",
+            ),
+            (
+                "base",
+                "[package]\nname = \"base\"\nversion.workspace = true\npublish = [\"dev\"]\n",
+            ),
+            (
+                "internal",
+                "[package]\nname = \"internal\"\nversion = \"0.1.0\"\npublish = false\n",
+            ),
+            (
+                "excluded",
+                "[package]\nname = \"excluded\"\nversion = \"0.1.0\"\n",
+            ),
+        ] {
+            std::fs::create_dir_all(dir.path().join("crates").join(name)).unwrap();
+            std::fs::write(
+                dir.path().join("crates").join(name).join("Cargo.toml"),
+                manifest,
+            )
+            .unwrap();
+        }
+
+        let crates = workspace_crates(dir.path()).unwrap();
+
+        let order = crates.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        let position = |name: &str| order.iter().position(|n| *n == name).unwrap();
+        assert_eq!(order.len(), 4, "{order:?}");
+        assert!(position("base") < position("middle"));
+        assert!(position("middle") < position("app"));
+
+        let middle = &crates[position("middle")];
+        assert_eq!(middle.path, PathBuf::from("crates/middle"));
+        assert_eq!(middle.dependencies, vec!["base".to_string()]);
+        assert!(!crates[position("internal")].publish);
+
+        let base = &crates[position("base")];
+        assert_eq!(base.version.as_deref(), Some("0.1.0"));
+        assert_eq!(base.registry.as_deref(), Some("dev"));
+        assert_eq!(middle.version.as_deref(), Some("0.2.0"));
+    }
+
+    #[test]
+    fn set_version_of_one_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            r#"[workspace]
+members = ["crates/*"]
+
+[workspace.dependencies]
+a = { version = "0.1.0", path = "crates/a" }
+b = { version = "0.4.0", path = "crates/b" }
+"#,
+        )
+        .unwrap();
+        for (name, version) in [("a", "0.1.0"), ("b", "0.4.0")] {
+            std::fs::create_dir_all(dir.path().join("crates").join(name)).unwrap();
+            std::fs::write(
+                dir.path().join("crates").join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n"),
+            )
+            .unwrap();
+        }
+
+        let changes = set_crate_version(dir.path(), "a", &semver::Version::new(0, 1, 1)).unwrap();
+
+        assert_eq!(
+            changes.iter().map(|c| c.key.as_str()).collect::<Vec<_>>(),
+            vec!["workspace.dependencies.a.version", "package.version"]
+        );
+        let b = std::fs::read_to_string(dir.path().join("crates/b/Cargo.toml")).unwrap();
+        assert!(b.contains("version = \"0.4.0\""));
+    }
+
+    #[test]
+    fn set_version_in_single_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"single\"\nversion = \"1.2.3\"\n",
+        )
+        .unwrap();
+
+        let changes =
+            set_workspace_version(dir.path(), &semver::Version::parse("1.3.0-dev.0").unwrap())
+                .unwrap();
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].from, "1.2.3");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(),
+            "[package]\nname = \"single\"\nversion = \"1.3.0-dev.0\"\n"
+        );
+
+        // Setting the same version again changes nothing.
+        let changes =
+            set_workspace_version(dir.path(), &semver::Version::parse("1.3.0-dev.0").unwrap())
+                .unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn include_changelog_in_package() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        for (name, manifest) in [
+            (
+                "listed",
+                "[package]\nname = \"listed\"\ninclude = [\"src/**/*\"]\n",
+            ),
+            (
+                "matched",
+                "[package]\nname = \"matched\"\ninclude = [\"/*.md\"]\n",
+            ),
+            ("default", "[package]\nname = \"default\"\n"),
+        ] {
+            std::fs::create_dir_all(dir.path().join("crates").join(name)).unwrap();
+            std::fs::write(
+                dir.path().join("crates").join(name).join("Cargo.toml"),
+                manifest,
+            )
+            .unwrap();
+        }
+
+        for name in ["listed", "matched", "default"] {
+            include_in_package(dir.path(), name, "CHANGELOG.md").unwrap();
+        }
+
+        let read = |name: &str| {
+            std::fs::read_to_string(dir.path().join("crates").join(name).join("Cargo.toml"))
+                .unwrap()
+        };
+        assert_eq!(
+            read("listed"),
+            "[package]\nname = \"listed\"\ninclude = [\"src/**/*\", \"CHANGELOG.md\"]\n"
+        );
+        assert_eq!(
+            read("matched"),
+            "[package]\nname = \"matched\"\ninclude = [\"/*.md\"]\n"
+        );
+        assert_eq!(read("default"), "[package]\nname = \"default\"\n");
+        assert!(include_in_package(dir.path(), "missing", "CHANGELOG.md").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! A side-effect free plan for the next release.
+//!
+//! Planning reads the repository and its configuration, but does not change anything. The plan
+//! can be reviewed, or printed as JSON, before it is applied by [`crate::execute_release_plan`].
+//!
+//! Progress is reported on stderr while planning, so that the plan itself can be written to
+//! stdout.
+
+use crate::cliff::CliffContext;
+use crate::manifest::WorkspaceCrate;
+use crate::prepare_release::get_released_version_tag;
+use crate::utils::get_revision_for_tag;
+use anyhow::Context;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// The plan for releasing the unreleased changes on the current branch.
+///
+/// There is a single plan for the whole workspace, or one plan for each crate with unreleased
+/// changes when crates are versioned independently.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReleasePlan {
+    /// The crate that this plan releases, when crates are versioned independently.
+    pub crate_name: Option<String>,
+    /// The tag of the previous release, if there is one.
+    pub previous_tag: Option<String>,
+    /// The tag for the next release.
+    pub next_tag: String,
+    /// The version for the next release.
+    pub next_version: String,
+    /// Why the next version was chosen.
+    pub bump_reason: BumpReason,
+    /// The unreleased commits, grouped as they will be in the changelog.
+    pub commits: Vec<CommitGroup>,
+    /// The crates that will be released at the next version.
+    pub crates: Vec<String>,
+    /// The previous release that semver checks will run against.
+    ///
+    /// This is `None` when there is no previous release or when semver checks are skipped.
+    pub semver_baseline: Option<SemverBaseline>,
+    #[serde(skip)]
+    pub(crate) changelog_path: PathBuf,
+    #[serde(skip)]
+    pub(crate) changelog: String,
+    #[serde(skip)]
+    pub(crate) crate_changelogs: Vec<CrateChangelog>,
+}
+
+/// The changelog for a single crate, written alongside the changelog for the workspace.
+#[derive(Debug, Clone)]
+pub(crate) struct CrateChangelog {
+    pub(crate) crate_name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) changelog: String,
+}
+
+/// The reason that the next version was chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BumpReason {
+    /// The version was forced.
+    Forced,
+    /// The next pre-release on a channel was requested.
+    PreRelease,
+    /// A bump of this kind was requested, from the previous release.
+    Requested(ReleaseType),
+    /// There is no previous release, so the initial version from the configuration is used.
+    Initial,
+    /// The unreleased changes contain a breaking change.
+    BreakingChange,
+    /// The unreleased changes contain a new feature.
+    Feature,
+    /// The unreleased changes only contain fixes or other changes.
+    Patch,
+}
+
+/// How the version of the next release is chosen.
+///
+/// By default, `git-cliff` picks the next semver version from the unreleased changes.
+#[derive(Debug, Clone, Default)]
+pub struct VersionOptions {
+    /// Force the next version, given as a version or a release tag.
+    ///
+    /// An empty string is treated the same as `None`.
+    pub force_version: Option<String>,
+    /// Do not warn when the forced version skips past the next major, minor or patch version.
+    pub allow_version_skip: bool,
+    /// Release the next pre-release on a channel. Cannot be combined with `force_version`.
+    pub pre_release: Option<PreRelease>,
+    /// Apply this kind of bump to the previous release, rather than picking it from the commits.
+    ///
+    /// Cannot be combined with `force_version` or `pre_release`.
+    pub bump: Option<ReleaseType>,
+}
+
+/// The channel of a pre-release, which is the first identifier of its pre-release version.
+///
+/// Channels sort by name under semver precedence, so `alpha` < `beta` < `dev` < `rc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreRelease {
+    Dev,
+    Alpha,
+    Beta,
+    Rc,
+}
+
+impl PreRelease {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PreRelease::Dev => "dev",
+            PreRelease::Alpha => "alpha",
+            PreRelease::Beta => "beta",
+            PreRelease::Rc => "rc",
+        }
+    }
+}
+
+/// A group of commits, in the order that they appear in the changelog.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitGroup {
+    /// The name of the group, from the `git-cliff` commit parsers.
+    pub name: String,
+    /// The commits in the group.
+    pub commits: Vec<PlannedCommit>,
+}
+
+/// A commit that will be included in the next release.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedCommit {
+    /// The commit hash.
+    pub id: String,
+    /// The first line of the commit message.
+    pub message: String,
+    /// Whether the commit is marked as a breaking change.
+    pub breaking: bool,
+}
+
+/// The previous release that semver checks run against.
+#[derive(Debug, Clone, Serialize)]
+pub struct SemverBaseline {
+    /// The tag of the previous release.
+    pub tag: String,
+    /// The commit that the tag points to.
+    pub revision: String,
+    /// The kind of release that the next version is, compared to the previous release.
+    pub release_type: ReleaseType,
+}
+
+/// The kind of release, as understood by `cargo-semver-checks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseType {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl ReleaseType {
+    /// Compare two versions by cargo's compatibility rules, ignoring any pre-release identifiers.
+    ///
+    /// The leftmost non-zero component of the previous version is treated as its major version, so
+    /// `0.1.3` to `0.2.0` is a major release and `0.1.3` to `0.1.4` is a minor release, as
+    /// `cargo-semver-checks` expects. Moving between pre-releases of the same version, or from a
+    /// pre-release to its stable version, is treated as a patch release.
+    fn between(previous: &semver::Version, next: &semver::Version) -> Self {
+        let previous_parts = [previous.major, previous.minor, previous.patch];
+        let next_parts = [next.major, next.minor, next.patch];
+        let Some(changed) = (0..3).find(|i| previous_parts[*i] != next_parts[*i]) else {
+            return ReleaseType::Patch;
+        };
+        let compatible_from = (0..2).find(|i| previous_parts[*i] != 0).unwrap_or(2);
+
+        match changed.saturating_sub(compatible_from) {
+            0 => ReleaseType::Major,
+            1 => ReleaseType::Minor,
+            _ => ReleaseType::Patch,
+        }
+    }
+
+    /// Bump a version by this kind of release.
+    ///
+    /// A pre-release is bumped to its own version when that is already a bump of this kind, so
+    /// a patch bump of `0.5.0-dev.3` is `0.5.0`, but a minor bump of `0.5.1-dev.0` is `0.6.0`.
+    pub(crate) fn bump(&self, version: &semver::Version) -> semver::Version {
+        let pre_release = !version.pre.is_empty();
+        let (major, minor, patch) = (version.major, version.minor, version.patch);
+
+        match self {
+            ReleaseType::Major if pre_release && minor == 0 && patch == 0 => {
+                semver::Version::new(major, 0, 0)
+            }
+            ReleaseType::Major => semver::Version::new(major + 1, 0, 0),
+            ReleaseType::Minor if pre_release && patch == 0 => {
+                semver::Version::new(major, minor, 0)
+            }
+            ReleaseType::Minor => semver::Version::new(major, minor + 1, 0),
+            ReleaseType::Patch if pre_release => semver::Version::new(major, minor, patch),
+            ReleaseType::Patch => semver::Version::new(major, minor, patch + 1),
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseType::Major => "major",
+            ReleaseType::Minor => "minor",
+            ReleaseType::Patch => "patch",
+        }
+    }
+}
+
+impl ReleasePlan {
+    /// Build the plan for the unreleased changes in a [`CliffContext`].
+    ///
+    /// The context must have unreleased changes. The `crates` are the names of the crates that will
+    /// be released at the next version. The release tags are the `tag_prefix` followed by the
+    /// version.
+    pub(crate) fn from_context(
+        repository: &git2::Repository,
+        context: &CliffContext,
+        tag_prefix: &str,
+        crate_scope: Option<&WorkspaceCrate>,
+        crates: Vec<String>,
+        forced: bool,
+        skip_semver_checks: bool,
+    ) -> anyhow::Result<Self> {
+        let next_tag = context
+            .next_version_tag()
+            .context("No unreleased changes to plan a release for")?;
+
+        let next_version = parse_tag_version(next_tag, tag_prefix)?;
+        let previous_tag = context.released_version_tag().map(ToString::to_string);
+
+        let commits = context.unreleased_commits();
+        let bump_reason = if forced {
+            BumpReason::Forced
+        } else if previous_tag.is_none() {
+            BumpReason::Initial
+        } else if commits
+            .iter()
+            .any(|c| c.conv.as_ref().is_some_and(|conv| conv.breaking()))
+        {
+            BumpReason::BreakingChange
+        } else if commits.iter().any(|c| {
+            c.conv
+                .as_ref()
+                .is_some_and(|conv| conv.type_().as_str() == "feat")
+        }) {
+            BumpReason::Feature
+        } else {
+            BumpReason::Patch
+        };
+
+        let semver_baseline = if skip_semver_checks {
+            None
+        } else {
+            match get_released_version_tag(context) {
+                Ok(tag) => {
+                    eprintln!("Retrieving revision for tag: {}", tag);
+                    let revision = get_revision_for_tag(repository, &tag)?;
+                    let previous_version = parse_tag_version(&tag, tag_prefix)?;
+                    Some(SemverBaseline {
+                        release_type: ReleaseType::between(&previous_version, &next_version),
+                        tag,
+                        revision,
+                    })
+                }
+                Err(e) => {
+                    eprintln!("No previous release found, semver checks will be skipped: {e:?}");
+                    None
+                }
+            }
+        };
+
+        Ok(ReleasePlan {
+            crate_name: crate_scope.map(|scope| scope.name.clone()),
+            previous_tag,
+            next_tag: next_tag.to_string(),
+            next_version: next_version.to_string(),
+            bump_reason,
+            commits: group_commits(commits),
+            crates,
+            semver_baseline,
+            changelog_path: context.changelog_path().to_path_buf(),
+            changelog: context.render_changelog()?,
+            crate_changelogs: Vec::new(),
+        })
+    }
+}
+
+impl Display for ReleasePlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.crate_name {
+            Some(crate_name) => writeln!(f, "Release {crate_name} {}", self.next_tag)?,
+            None => writeln!(f, "Release {}", self.next_tag)?,
+        }
+        writeln!(
+            f,
+            "  Previous release: {}",
+            self.previous_tag.as_deref().unwrap_or("none")
+        )?;
+        writeln!(f, "  Bump reason: {:?}", self.bump_reason)?;
+        writeln!(f, "  Crates: {}", self.crates.join(", "))?;
+        if !self.crate_changelogs.is_empty() {
+            writeln!(
+                f,
+                "  Crate changelogs: {}",
+                self.crate_changelogs
+                    .iter()
+                    .map(|c| c.crate_name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        match &self.semver_baseline {
+            Some(baseline) => writeln!(
+                f,
+                "  Semver checks: {} release against {} ({})",
+                baseline.release_type.as_str(),
+                baseline.tag,
+                baseline.revision
+            )?,
+            None => writeln!(f, "  Semver checks: none")?,
+        }
+        for group in &self.commits {
+            writeln!(f, "  {}:", group.name)?;
+            for commit in &group.commits {
+                let short_id = commit.id.get(..7).unwrap_or(&commit.id);
+                let breaking = if commit.breaking { " (breaking)" } else { "" };
+                writeln!(f, "    - {short_id} {}{breaking}", commit.message)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the version from a release tag with the given prefix.
+pub(crate) fn parse_tag_version(tag: &str, tag_prefix: &str) -> anyhow::Result<semver::Version> {
+    let version = tag
+        .strip_prefix(tag_prefix)
+        .with_context(|| format!("Expected tag to start with '{tag_prefix}', got: {tag}"))?;
+
+    semver::Version::parse(version).with_context(|| format!("Invalid version in tag: {tag}"))
+}
+
+/// Group commits the way the changelog template does, sorted by the raw group name.
+///
+/// Group names may start with an HTML comment that is only used for sorting, which is removed.
+fn group_commits(commits: &[git_cliff_core::commit::Commit]) -> Vec<CommitGroup> {
+    let mut groups = std::collections::BTreeMap::<&str, Vec<PlannedCommit>>::new();
+    for commit in commits {
+        groups
+            .entry(commit.group.as_deref().unwrap_or("Other"))
+            .or_default()
+            .push(PlannedCommit {
+                id: commit.id.clone(),
+                message: commit
+                    .message
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                breaking: commit.conv.as_ref().is_some_and(|conv| conv.breaking()),
+            });
+    }
+
+    groups
+        .into_iter()
+        .map(|(name, commits)| CommitGroup {
+            name: strip_sort_comment(name).to_string(),
+            commits,
+        })
+        .collect()
+}
+
+fn strip_sort_comment(group: &str) -> &str {
+    match group.trim_start().strip_prefix("<!--") {
+        Some(rest) => rest
+            .split_once("-->")
+            .map_or(group, |(_, name)| name.trim()),
+        None => group.trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_type_between_versions() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+
+        assert_eq!(
+            ReleaseType::between(&v("0.1.0"), &v("1.0.0")),
+            ReleaseType::Major
+        );
+        assert_eq!(
+            ReleaseType::between(&v("1.2.3"), &v("1.3.0")),
+            ReleaseType::Minor
+        );
+        assert_eq!(
+            ReleaseType::between(&v("1.2.3"), &v("1.2.4")),
+            ReleaseType::Patch
+        );
+
+        // Before 1.0, the leftmost non-zero component is the major version.
+        assert_eq!(
+            ReleaseType::between(&v("0.1.3"), &v("0.2.0")),
+            ReleaseType::Major
+        );
+        assert_eq!(
+            ReleaseType::between(&v("0.1.3"), &v("0.1.4")),
+            ReleaseType::Minor
+        );
+        assert_eq!(
+            ReleaseType::between(&v("0.0.1"), &v("0.0.2")),
+            ReleaseType::Major
+        );
+
+        // Pre-releases are compared by the version they are a pre-release of.
+        assert_eq!(
+            ReleaseType::between(&v("0.1.3"), &v("0.2.0-dev.0")),
+            ReleaseType::Major
+        );
+        assert_eq!(
+            ReleaseType::between(&v("1.2.3"), &v("1.3.0-rc.0")),
+            ReleaseType::Minor
+        );
+        assert_eq!(
+            ReleaseType::between(&v("0.2.0-dev.0"), &v("0.2.0-dev.1")),
+            ReleaseType::Patch
+        );
+        assert_eq!(
+            ReleaseType::between(&v("0.2.0-rc.1"), &v("0.2.0")),
+            ReleaseType::Patch
+        );
+    }
+
+    #[test]
+    fn bump_versions() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+
+        assert_eq!(ReleaseType::Major.bump(&v("0.4.2")), v("1.0.0"));
+        assert_eq!(ReleaseType::Minor.bump(&v("0.4.2")), v("0.5.0"));
+        assert_eq!(ReleaseType::Patch.bump(&v("0.4.2")), v("0.4.3"));
+
+        assert_eq!(ReleaseType::Patch.bump(&v("0.5.0-dev.3")), v("0.5.0"));
+        assert_eq!(ReleaseType::Minor.bump(&v("0.5.0-dev.3")), v("0.5.0"));
+        assert_eq!(ReleaseType::Minor.bump(&v("0.5.1-dev.0")), v("0.6.0"));
+        assert_eq!(ReleaseType::Major.bump(&v("0.5.0-rc.1")), v("1.0.0"));
+        assert_eq!(ReleaseType::Major.bump(&v("2.0.0-rc.1")), v("2.0.0"));
+    }
+
+    #[test]
+    fn strip_group_sort_comment() {
+        assert_eq!(strip_sort_comment("<!-- 00 -->Features"), "Features");
+        assert_eq!(strip_sort_comment("Bug Fixes"), "Bug Fixes");
+    }
+}
@@ -1,172 +1,426 @@
-use crate::utils::get_version_from_cliff_output;
+use crate::cliff::CliffContext;
+use crate::error::{ReleaseError, run_tool, stderr_tail, tool_error};
+use crate::manifest::WorkspaceCrate;
+use crate::manifest::{set_crate_version, set_workspace_version};
+use crate::plan::{PreRelease, ReleaseType, parse_tag_version};
 use anyhow::Context;
 use std::path::Path;
 
-pub(crate) fn generate_changelog(
-    dir: impl AsRef<Path>,
-    cliff_config: &str,
-    force_tag: &Option<String>,
-) -> anyhow::Result<()> {
-    println!("Generating changelog");
+/// Write the changelog that was rendered for a release.
+pub(crate) fn write_changelog(path: impl AsRef<Path>, changelog: &str) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    println!("Writing changelog to {}", path.display());
 
-    let mut command = common_git_cliff_command(&dir, cliff_config, force_tag);
+    std::fs::write(path, changelog).context("Failed to write changelog")
+}
 
-    command.arg("--unreleased");
+pub(crate) fn get_next_version(context: &CliffContext) -> anyhow::Result<String> {
+    eprintln!("Retrieving next version");
 
-    if !dir.as_ref().join("CHANGELOG.md").exists() {
-        println!("Changelog does not exist, creating a new one.");
-        command.arg("--output");
-    } else {
-        println!("Changelog already exists, prepending new changes.");
-        command.arg("--prepend").arg("CHANGELOG.md");
+    match context.next_version_tag() {
+        Some(tag) => Ok(tag.to_string()),
+        None => match context.released_version_tag() {
+            Some(tag) => anyhow::bail!("No unreleased changes found since {tag}"),
+            None => anyhow::bail!("No changes found to release"),
+        },
     }
+}
 
-    if let Some(tag) = force_tag {
-        println!("Forcing tag: {}", tag);
-        command.arg("--tag").arg(tag);
-    } else {
-        command.arg("--bump");
+/// Find the tag for the next pre-release on the `channel`, such as `v0.5.0-dev.4` after
+/// `v0.5.0-dev.3`.
+///
+/// The version is bumped from the previous stable release, using all the changes since then. If the
+/// latest release on the branch is already a pre-release of that version or a later one, the series
+/// is continued. Returns `None` when there are no changes since the latest release.
+pub(crate) fn get_next_pre_release_tag(
+    dir: impl AsRef<Path>,
+    cliff_config: &str,
+    tag_prefix: &str,
+    crate_scope: Option<&WorkspaceCrate>,
+    channel: PreRelease,
+) -> anyhow::Result<Option<String>> {
+    eprintln!("Retrieving next {} pre-release", channel.as_str());
+
+    let latest = CliffContext::load(&dir, cliff_config, tag_prefix, &None, false, crate_scope)?;
+    if latest.next_version_tag().is_none() {
+        return Ok(None);
     }
+    let previous = latest
+        .released_version_tag()
+        .map(|tag| parse_tag_version(tag, tag_prefix))
+        .transpose()?;
+
+    let stable = CliffContext::load(&dir, cliff_config, tag_prefix, &None, true, crate_scope)?;
+    let base = parse_tag_version(
+        stable
+            .next_version_tag()
+            .context("No changes found since the previous stable release")?,
+        tag_prefix,
+    )?;
+
+    let next = next_pre_release(&base, previous.as_ref(), channel)?;
+
+    Ok(Some(format!("{tag_prefix}{next}")))
+}
 
-    let status = command.status().context("git-cliff failed to run")?;
+/// Pick the next pre-release of `base` on the `channel`, continuing from the `previous` release.
+fn next_pre_release(
+    base: &semver::Version,
+    previous: Option<&semver::Version>,
+    channel: PreRelease,
+) -> anyhow::Result<semver::Version> {
+    let mut next = semver::Version::new(base.major, base.minor, base.patch);
+    let mut number = 0;
+
+    if let Some(previous) = previous.filter(|p| !p.pre.is_empty()) {
+        let previous_base = semver::Version::new(previous.major, previous.minor, previous.patch);
+        if previous_base >= next {
+            next = previous_base;
+            if let Some((id, n)) = previous.pre.as_str().split_once('.')
+                && id == channel.as_str()
+                && let Ok(n) = n.parse::<u64>()
+            {
+                number = n + 1;
+            }
+        }
+    }
 
-    if !status.success() {
-        anyhow::bail!("git-cliff command failed with status: {}", status);
+    next.pre = semver::Prerelease::new(&format!("{}.{number}", channel.as_str()))
+        .context("Invalid pre-release")?;
+
+    if let Some(previous) = previous
+        && next <= *previous
+    {
+        anyhow::bail!(ReleaseError::InvalidVersion {
+            version: next.to_string(),
+            message: format!(
+                "The next {} pre-release would be {next}, which does not come after the previous release {previous}. Use a later channel, or force the version",
+                channel.as_str()
+            ),
+        });
     }
 
-    Ok(())
+    Ok(next)
 }
 
-pub(crate) fn get_next_version(
+/// Find the tag for the next version by applying the `bump` to the previous release on the branch.
+///
+/// Returns `None` when there are no changes since the previous release. Fails if there is no
+/// previous release to bump from.
+pub(crate) fn get_bumped_version_tag(
     dir: impl AsRef<Path>,
     cliff_config: &str,
-    force_tag: &Option<String>,
-) -> anyhow::Result<String> {
-    println!("Retrieving next version");
+    tag_prefix: &str,
+    crate_scope: Option<&WorkspaceCrate>,
+    bump: ReleaseType,
+) -> anyhow::Result<Option<String>> {
+    eprintln!("Applying a {} bump to the previous release", bump.as_str());
+
+    let context = CliffContext::load(&dir, cliff_config, tag_prefix, &None, false, crate_scope)?;
+    if context.next_version_tag().is_none() {
+        return Ok(None);
+    }
+    let released_tag = get_released_version_tag(&context)
+        .with_context(|| format!("Cannot apply a {} bump", bump.as_str()))?;
+    let next = bump.bump(&parse_tag_version(&released_tag, tag_prefix)?);
 
-    let mut command = common_git_cliff_command(&dir, cliff_config, force_tag);
+    Ok(Some(format!("{tag_prefix}{next}")))
+}
 
-    command
-        .arg("--unreleased")
-        .arg("--bump")
-        .arg("--context")
-        .stdout(std::process::Stdio::piped());
+/// Check that a forced version tag can follow the existing release tags.
+///
+/// Fails if the tag already exists anywhere in the repository, or if the version does not come
+/// after the latest release that is reachable from HEAD. Warns if the version skips past the next
+/// major, minor or patch version, unless `allow_version_skip` is set.
+pub(crate) fn check_forced_version(
+    repository: &git2::Repository,
+    tag_prefix: &str,
+    force_tag: &str,
+    allow_version_skip: bool,
+) -> anyhow::Result<()> {
+    let version = parse_tag_version(force_tag, tag_prefix)?;
+    let head = repository
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .context("Failed to find the HEAD commit")?
+        .id();
+
+    let mut latest: Option<(semver::Version, String)> = None;
+    let tag_names = repository
+        .tag_names(Some(&format!("{tag_prefix}*")))
+        .context("Failed to list release tags")?;
+    for name in tag_names.iter().flatten() {
+        let Ok(tag_version) = parse_tag_version(name, tag_prefix) else {
+            continue;
+        };
+        if tag_version == version {
+            anyhow::bail!(ReleaseError::TagConflict {
+                tag: name.to_string(),
+                message: format!("Cannot force version {version}, the tag {name} already exists"),
+            });
+        }
+
+        let commit = repository
+            .revparse_single(&format!("refs/tags/{name}"))
+            .and_then(|object| object.peel_to_commit())
+            .with_context(|| format!("Failed to find the commit for tag {name}"))?
+            .id();
+        let reachable = commit == head
+            || repository
+                .graph_descendant_of(head, commit)
+                .context("Failed to check the history of HEAD")?;
+        if reachable && latest.as_ref().is_none_or(|(v, _)| tag_version > *v) {
+            latest = Some((tag_version, name.to_string()));
+        }
+    }
+
+    let Some((latest, latest_tag)) = latest else {
+        return Ok(());
+    };
+    if version < latest {
+        anyhow::bail!(ReleaseError::InvalidVersion {
+            version: version.to_string(),
+            message: format!(
+                "Cannot force version {version}, it comes before the latest release {latest_tag}"
+            ),
+        });
+    }
 
-    if let Some(tag) = force_tag {
-        command.arg("--tag").arg(tag);
+    if skips_a_version(&latest, &version) && !allow_version_skip {
+        emit_warning(
+            "Version Skipped",
+            &format!(
+                "The forced version {version} skips past the next version after {latest_tag}. Pass --allow-version-skip if this is intentional."
+            ),
+        );
     }
 
-    let output = command.output().context("git-cliff failed to run")?;
+    Ok(())
+}
 
-    get_version_from_cliff_output(&output.stdout)
+/// Whether `next` is further from `previous` than a single major, minor or patch bump.
+///
+/// Pre-release identifiers are ignored, and a pre-release may be followed by its stable version.
+fn skips_a_version(previous: &semver::Version, next: &semver::Version) -> bool {
+    let previous_base = semver::Version::new(previous.major, previous.minor, previous.patch);
+    let next_base = semver::Version::new(next.major, next.minor, next.patch);
+
+    let bumps = [
+        semver::Version::new(previous.major + 1, 0, 0),
+        semver::Version::new(previous.major, previous.minor + 1, 0),
+        semver::Version::new(previous.major, previous.minor, previous.patch + 1),
+    ];
+    let pre_release_base = !previous.pre.is_empty() && next_base == previous_base;
+
+    !pre_release_base && !bumps.contains(&next_base)
+}
+
+/// Print a warning to stderr, as a workflow annotation when running in GitHub Actions.
+///
+/// Actions reads workflow commands from stderr too, and stdout is kept for output such as the
+/// JSON release plan.
+pub(crate) fn emit_warning(title: &str, msg: &str) {
+    if std::env::var("GITHUB_ACTIONS").as_deref() == Ok("true") {
+        eprintln!("::warning title={title}::{msg}");
+    } else {
+        eprintln!("WARNING: {msg}");
+    }
 }
 
 pub(crate) fn set_version(dir: impl AsRef<Path>, version: &str) -> anyhow::Result<()> {
     let version = version.trim_start_matches('v');
     println!("Setting version to {}", version);
 
-    let mut command = std::process::Command::new("cargo");
+    let version = semver::Version::parse(version).context("Invalid version")?;
+    let changes = set_workspace_version(dir, &version).context("Failed to set version")?;
 
-    command
-        .current_dir(dir)
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .arg("workspaces")
-        .arg("version")
-        .arg("--no-git-commit")
-        .arg("--yes")
-        .arg("custom")
-        .arg(version)
-        .arg("--force")
-        .arg("*");
-
-    let status = command
-        .status()
-        .context("Failed to run cargo workspaces version")?;
-
-    if !status.success() {
-        anyhow::bail!(
-            "cargo workspaces version command failed with status: {}",
-            status
-        );
+    if changes.is_empty() {
+        println!("Version is already set, no manifests changed.");
+    }
+    for change in changes {
+        println!("Updated {change}");
     }
 
     Ok(())
 }
 
-pub(crate) fn get_released_version_tag(
+pub(crate) fn set_independent_version(
     dir: impl AsRef<Path>,
-    cliff_config: &str,
-    force_tag: &Option<String>,
-) -> anyhow::Result<String> {
-    println!("Retrieving released version tag");
+    crate_name: &str,
+    version: &str,
+) -> anyhow::Result<()> {
+    println!("Setting version of {crate_name} to {version}");
 
-    let mut command = common_git_cliff_command(&dir, cliff_config, force_tag);
+    let version = semver::Version::parse(version).context("Invalid version")?;
+    let changes =
+        set_crate_version(dir, crate_name, &version).context("Failed to set crate version")?;
 
-    command
-        .arg("--latest")
-        .arg("--context")
-        .stdout(std::process::Stdio::piped());
+    for change in changes {
+        println!("Updated {change}");
+    }
 
-    let output = command.output().context("git-cliff failed to run")?;
+    Ok(())
+}
 
-    get_version_from_cliff_output(&output.stdout)
+pub(crate) fn get_released_version_tag(context: &CliffContext) -> anyhow::Result<String> {
+    eprintln!("Retrieving released version tag");
+
+    context
+        .released_version_tag()
+        .map(ToString::to_string)
+        .context("No release tag found on the current branch")
 }
 
 pub(crate) fn run_semver_checks(
     dir: impl AsRef<Path>,
+    package: Option<&str>,
     against_revision: &str,
+    release_type: ReleaseType,
     i_am_so_sorry_but_my_features_clash: bool,
 ) -> anyhow::Result<()> {
     let mut command = std::process::Command::new("cargo");
+    command.current_dir(&dir).arg("semver-checks");
+
+    match package {
+        Some(package) => command.arg("--package").arg(package),
+        None => command.arg("--workspace"),
+    };
+
     command
-        .current_dir(&dir)
-        .arg("semver-checks")
-        .arg("--workspace")
         .arg("--baseline-rev")
         .arg(against_revision)
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit());
+        .arg("--release-type")
+        .arg(release_type.as_str());
 
     if i_am_so_sorry_but_my_features_clash {
         command.arg("--default-features");
     }
 
-    let status = command
-        .status()
-        .context("Failed to run cargo semver-checks")?;
-
-    if !status.success() {
-        anyhow::bail!("cargo semver-checks command failed with status: {}", status);
+    let output = run_tool("cargo", &mut command)?;
+
+    if !output.status.success() {
+        // Cargo fails in the same way when the subcommand is not installed, so check for it before
+        // blaming the changes.
+        let installed = std::process::Command::new("cargo")
+            .arg("semver-checks")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !installed {
+            return Err(tool_error(
+                "cargo-semver-checks",
+                std::io::ErrorKind::NotFound.into(),
+            ));
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        anyhow::bail!(ReleaseError::SemverViolation {
+            release_type,
+            message: format!(
+                "cargo semver-checks found changes that are not allowed in a {} release ({}): {}",
+                release_type.as_str(),
+                output.status,
+                stderr_tail(&stderr)
+            ),
+            stderr,
+        });
     }
 
     Ok(())
 }
 
-fn common_git_cliff_command(
-    dir: impl AsRef<Path>,
-    cliff_config: &str,
-    force_tag: &Option<String>,
-) -> std::process::Command {
-    let mut command = std::process::Command::new("git-cliff");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_forced_versions_against_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = git2::Repository::init(dir.path()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repository
+            .find_tree(repository.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let first = repository
+            .commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+            .unwrap();
+        let first = repository.find_commit(first).unwrap();
+        let second = repository
+            .commit(None, &signature, &signature, "second", &tree, &[&first])
+            .unwrap();
+        let second = repository.find_commit(second).unwrap();
+        repository
+            .tag_lightweight("v0.1.0", first.as_object(), false)
+            .unwrap();
+        // Not reachable from HEAD, such as a release from another branch.
+        repository
+            .tag_lightweight("v0.3.0", second.as_object(), false)
+            .unwrap();
+
+        check_forced_version(&repository, "v", "v0.2.0", false).unwrap();
+        check_forced_version(&repository, "v", "v0.1.1-rc.0", false).unwrap();
+
+        let err = check_forced_version(&repository, "v", "v0.3.0", false).unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{err}");
+        let err = check_forced_version(&repository, "v", "v0.0.9", false).unwrap_err();
+        assert!(
+            err.to_string().contains("before the latest release"),
+            "{err}"
+        );
+    }
 
-    command
-        .current_dir(dir)
-        .stderr(std::process::Stdio::inherit())
-        .stdout(std::process::Stdio::inherit())
-        .arg("--use-branch-tags");
+    #[test]
+    fn detect_skipped_versions() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
 
-    if url::Url::parse(cliff_config).is_ok() {
-        command.arg("--config-url").arg(cliff_config);
-    } else {
-        command.arg("--config").arg(cliff_config);
-    }
+        assert!(!skips_a_version(&v("0.4.2"), &v("0.4.3")));
+        assert!(!skips_a_version(&v("0.4.2"), &v("0.5.0-dev.0")));
+        assert!(!skips_a_version(&v("0.4.2"), &v("1.0.0")));
+        assert!(!skips_a_version(&v("0.5.0-rc.1"), &v("0.5.0")));
 
-    if force_tag.as_ref().is_some_and(|tag| !(tag.contains("-dev") || tag.contains("-rc"))) {
-        command.arg("--tag-pattern").arg("^v\\d+.\\d+.\\d+$");
-    } else {
-        command.arg("--tag-pattern").arg("^v\\d+.\\d+.\\d+");
+        assert!(skips_a_version(&v("0.4.2"), &v("0.4.4")));
+        assert!(skips_a_version(&v("0.4.2"), &v("0.6.0")));
+        assert!(skips_a_version(&v("0.4.2"), &v("0.5.1")));
+        assert!(skips_a_version(&v("1.2.3"), &v("3.0.0")));
     }
 
-    command
+    #[test]
+    fn pick_next_pre_release() {
+        let v = |s: &str| semver::Version::parse(s).unwrap();
+
+        // A new series starts from the bump since the previous stable release.
+        assert_eq!(
+            next_pre_release(&v("0.5.0"), Some(&v("0.4.2")), PreRelease::Rc).unwrap(),
+            v("0.5.0-rc.0")
+        );
+        assert_eq!(
+            next_pre_release(&v("0.1.0"), None, PreRelease::Dev).unwrap(),
+            v("0.1.0-dev.0")
+        );
+
+        // An existing series is continued.
+        assert_eq!(
+            next_pre_release(&v("0.5.0"), Some(&v("0.5.0-dev.3")), PreRelease::Dev).unwrap(),
+            v("0.5.0-dev.4")
+        );
+        assert_eq!(
+            next_pre_release(&v("0.5.0"), Some(&v("0.5.0-dev.3")), PreRelease::Rc).unwrap(),
+            v("0.5.0-rc.0")
+        );
+        assert_eq!(
+            next_pre_release(&v("0.5.0"), Some(&v("0.6.0-dev.1")), PreRelease::Dev).unwrap(),
+            v("0.6.0-dev.2")
+        );
+
+        // A breaking change after the series started moves to the next version.
+        assert_eq!(
+            next_pre_release(&v("0.6.0"), Some(&v("0.5.0-dev.3")), PreRelease::Dev).unwrap(),
+            v("0.6.0-dev.0")
+        );
+
+        // Going back to an earlier channel would not sort after the previous release.
+        let err =
+            next_pre_release(&v("0.5.0"), Some(&v("0.5.0-rc.1")), PreRelease::Beta).unwrap_err();
+        assert!(err.to_string().contains("0.5.0-beta.0"), "{err}");
+    }
 }
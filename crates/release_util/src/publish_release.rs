@@ -1,14 +1,48 @@
-use crate::RELEASE_LABEL;
+use crate::ReleaseConfig;
+use crate::error::{ReleaseError, run_tool, tool_failed};
+use crate::forge::{Forge, NewRelease};
+use crate::manifest::{WorkspaceCrate, workspace_crates};
+use crate::registry::Registry;
+use crate::utils::{GitRemote, get_revision_for_tag, remote_tag_revision, tag_refspec};
 use anyhow::Context;
-use std::path::Path;
+use regex::Regex;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
+
+/// The options for publishing a release with [`publish_release`](crate::publish_release).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishOptions {
+    /// Publish without checking that HEAD came from a pull request with the release label.
+    pub danger_skip_releasable_changes_check: bool,
+    /// Publish without creating a release on the forge.
+    pub danger_skip_create_release: bool,
+    /// Run every check and print the actions that would be taken, without taking them.
+    pub dry_run: bool,
+    /// Move a release tag that points to another commit to HEAD, as long as none of the crates in
+    /// the release are in their registry at the release version.
+    pub allow_retag: bool,
+}
+
+/// What [`publish_release`](crate::publish_release) did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// The release was published, or with [`PublishOptions::dry_run`], was checked and would
+    /// have been.
+    Published,
+    /// The HEAD commit is not from a merged pull request with the release label, so there was
+    /// nothing to publish.
+    NotReleasable,
+}
 
 /// Checks if the current HEAD commit is part of a merged pull request that is releasable.
 ///
-/// Determined by the presence of the `hra-release` label on the pull request that this change came
-/// from.
+/// Determined by the presence of the `release_label`, `hra-release` by default, on the pull request
+/// that this change came from.
 pub(crate) fn is_releasable_change(
     repository: &git2::Repository,
-    dir: impl AsRef<Path>,
+    forge: &dyn Forge,
+    release_label: &str,
 ) -> anyhow::Result<Option<u64>> {
     let head = repository
         .head()
@@ -16,69 +50,32 @@ pub(crate) fn is_releasable_change(
         .peel_to_commit()
         .context("Failed to retrieve HEAD commit")?;
 
-    let output = std::process::Command::new("gh")
-        .current_dir(&dir)
-        .arg("pr")
-        .arg("list")
-        .arg("--search")
-        .arg(head.id().to_string())
-        .arg("--state")
-        .arg("merged")
-        .arg("--json")
-        .arg("id,number,labels")
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::inherit())
-        .output()
-        .context("Failed to run `gh pr list`")?;
-
-    let matches = serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout)
-        .context("Failed to parse `gh pr list` output")?;
-
-    if matches.len() == 1 {
-        let values = &matches[0]
-            .as_object()
-            .context("Expected a JSON object value as PR list output")?;
-
-        let pr_number = values
-            .get("number")
-            .context("Missing 'number' in PR data")?
-            .as_number()
-            .context("Expected a number as the PR number value")?
-            .as_u64()
-            .expect("PR number should be a valid u64");
+    let pull_request = forge
+        .find_merged_pull_request(&head.id().to_string())
+        .context(ReleaseError::Forge {
+            message: "Failed to find the pull request for the HEAD commit".to_string(),
+        })?;
 
-        println!(
-            "Have labels for PR #{}: {:?}",
-            pr_number,
-            values.get("labels")
-        );
+    if let Some(pull_request) = pull_request {
+        let labels =
+            forge
+                .pull_request_labels(pull_request.number)
+                .context(ReleaseError::Forge {
+                    message: "Failed to get pull request labels".to_string(),
+                })?;
 
-        let labels = values
-            .get("labels")
-            .context("Missing 'labels' in PR data")?
-            .as_array()
-            .context("Expected an array for labels")?
-            .iter()
-            .map(|v| {
-                v.as_object()
-                    .context("Expected label object")?
-                    .get("name")
-                    .context("Expected label to have a name")?
-                    .as_str()
-                    .context("Expected label name to be a string")
-            })
-            .collect::<anyhow::Result<Vec<_>>>()?;
+        println!("Have labels for PR #{}: {:?}", pull_request.number, labels);
 
-        if labels.contains(&RELEASE_LABEL) {
+        if labels.iter().any(|label| label == release_label) {
             println!(
-                "Found releasable PR #{} with 'hra-release' label",
-                pr_number
+                "Found releasable PR #{} with '{release_label}' label",
+                pull_request.number
             );
-            return Ok(Some(pr_number));
+            return Ok(Some(pull_request.number));
         } else {
             println!(
-                "PR #{} is not releasable due to missing 'hra-release' label",
-                pr_number
+                "PR #{} is not releasable due to missing '{release_label}' label",
+                pull_request.number
             );
         }
     }
@@ -87,48 +84,750 @@ pub(crate) fn is_releasable_change(
     Ok(None)
 }
 
-pub(crate) fn publish(dir: impl AsRef<Path>) -> anyhow::Result<()> {
-    let status = std::process::Command::new("cargo")
-        .current_dir(dir)
-        .arg("workspaces")
-        .arg("publish")
-        .arg("--allow-branch")
-        .arg("(main|release)*")
-        .arg("--publish-as-is")
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .status()
-        .context("Failed to run workspace publish")?;
+/// Check that every publishable crate, and the top entry of the changelog, are at the `version`
+/// that is about to be tagged.
+///
+/// The versions are resolved as cargo would, including `version.workspace = true`, so that a crate
+/// with a version that has drifted from the workspace is caught before anything is tagged.
+pub(crate) fn check_release_versions(dir: impl AsRef<Path>, version: &str) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+
+    let mismatched = workspace_crates(dir)?
+        .into_iter()
+        .filter(|c| c.publish && c.version.as_deref() != Some(version))
+        .map(|c| {
+            format!(
+                "{} {}",
+                c.name,
+                c.version.as_deref().unwrap_or("(no version)")
+            )
+        })
+        .collect::<Vec<_>>();
+    if !mismatched.is_empty() {
+        anyhow::bail!(ReleaseError::InvalidVersion {
+            version: version.to_string(),
+            message: format!(
+                "Publishable crates must be at the release version {version}, but found: {}",
+                mismatched.join(", ")
+            ),
+        });
+    }
+
+    let changelog_path = dir.join("CHANGELOG.md");
+    let changelog = std::fs::read_to_string(&changelog_path)
+        .with_context(|| format!("Failed to read {}", changelog_path.display()))?;
+    match latest_changelog_version(&changelog) {
+        Some(latest) if latest == version => Ok(()),
+        Some(latest) => anyhow::bail!(ReleaseError::InvalidVersion {
+            version: version.to_string(),
+            message: format!(
+                "The latest entry in CHANGELOG.md is for {latest}, but the release version is {version}"
+            ),
+        }),
+        None => anyhow::bail!(ReleaseError::InvalidVersion {
+            version: version.to_string(),
+            message: format!(
+                "The latest entry in CHANGELOG.md has no version, but the release version is {version}"
+            ),
+        }),
+    }
+}
+
+/// Find the version in the heading of the first entry in a changelog, such as
+/// `## [0.2.0](https://...) - 2025-01-01`.
+fn latest_changelog_version(changelog: &str) -> Option<&str> {
+    let heading = changelog.lines().find(|line| line.starts_with("## "))?;
+
+    heading_version(heading)
+}
+
+/// Find the version in a changelog entry heading.
+fn heading_version(heading: &str) -> Option<&str> {
+    let version = Regex::new(r"\d+\.\d+\.\d+(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?")
+        .expect("valid version pattern");
+
+    version.find(heading).map(|m| m.as_str())
+}
+
+/// Find the body of the changelog entry for `version`, without its heading.
+///
+/// The entry runs from its `## ` heading to the next one, or to the end of the changelog.
+fn changelog_section(changelog: &str, version: &str) -> Option<String> {
+    let mut lines = changelog
+        .lines()
+        .skip_while(|line| !(line.starts_with("## ") && heading_version(line) == Some(version)));
+    lines.next()?;
+
+    let section = lines
+        .take_while(|line| !line.starts_with("## "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let section = section.trim();
+
+    (!section.is_empty()).then(|| section.to_string())
+}
+
+/// Get the release notes for `version` from the changelog at `changelog_path`.
+///
+/// Returns `None` when the changelog has no entry for the version, so that the forge generates the
+/// notes instead.
+pub(crate) fn release_notes(changelog_path: &Path, version: &str) -> Option<String> {
+    let notes = std::fs::read_to_string(changelog_path)
+        .ok()
+        .and_then(|changelog| changelog_section(&changelog, version));
+    if notes.is_none() {
+        println!(
+            "No entry for {version} found in {}, the release notes will be generated instead",
+            changelog_path.display()
+        );
+    }
+
+    notes
+}
+
+/// The outcome of publishing the crates in a workspace.
+#[derive(Debug, Default)]
+pub(crate) struct PublishSummary {
+    /// The crates that were published, as `name@version`.
+    pub(crate) published: Vec<String>,
+    /// The crates that were already in their registry, as `name@version`.
+    pub(crate) skipped: Vec<String>,
+    /// The crate that failed to publish, as `name@version`.
+    pub(crate) failed: Option<String>,
+    /// The crates that were not attempted because an earlier crate failed, as `name@version`.
+    pub(crate) remaining: Vec<String>,
+}
+
+impl PublishSummary {
+    /// Whether any crate, from this publish or an earlier one, is in its registry.
+    pub(crate) fn any_uploaded(&self) -> bool {
+        !self.published.is_empty() || !self.skipped.is_empty()
+    }
+
+    pub(crate) fn print(&self) {
+        println!("Publish summary:");
+        println!("  Published: {}", list_or_none(&self.published));
+        println!(
+            "  Skipped, already published: {}",
+            list_or_none(&self.skipped)
+        );
+        if let Some(failed) = &self.failed {
+            println!("  Failed: {failed}");
+            println!("  Not attempted: {}", list_or_none(&self.remaining));
+        }
+    }
+}
+
+fn list_or_none(crates: &[String]) -> String {
+    if crates.is_empty() {
+        "none".to_string()
+    } else {
+        crates.join(", ")
+    }
+}
+
+/// Publish every publishable crate in the workspace, in dependency order.
+///
+/// Crates whose current version is already in the sparse index of their registry are skipped, so
+/// that a publish that failed part way through can be run again.
+///
+/// Nothing is uploaded from a branch that the branch policy in the `config` does not permit to
+/// release each crate's version, which replaces the `--allow-branch` guard of `cargo workspaces`.
+///
+/// The `status` is from [`publish_status`], at the start of the release. The `summary` is filled
+/// in as crates are published, so that it says how far publishing got if an error is returned.
+pub(crate) fn publish(
+    repository: &git2::Repository,
+    dir: impl AsRef<Path>,
+    config: &ReleaseConfig,
+    status: &[CrateStatus],
+    summary: &mut PublishSummary,
+) -> anyhow::Result<()> {
+    for (workspace_crate, version, published) in status {
+        if *published {
+            continue;
+        }
+        let version = semver::Version::parse(version)
+            .with_context(|| format!("Invalid version of {}: {version}", workspace_crate.name))?;
+        config
+            .check_branch_policy(repository, &version)
+            .with_context(|| format!("Refusing to publish {}", workspace_crate.name))?;
+    }
+
+    let mut crates = status.iter();
+    while let Some((workspace_crate, version, published)) = crates.next() {
+        let crate_version = format!("{}@{version}", workspace_crate.name);
+        if *published {
+            println!("{crate_version} is already published, skipping.");
+            summary.skipped.push(crate_version);
+            continue;
+        }
+
+        println!("Publishing {crate_version}");
+        let output = run_tool(
+            "cargo publish",
+            std::process::Command::new("cargo")
+                .current_dir(&dir)
+                .arg("publish")
+                .arg("--package")
+                .arg(&workspace_crate.name),
+        );
+
+        if !output.as_ref().is_ok_and(|output| output.status.success()) {
+            summary.failed = Some(crate_version.clone());
+            summary.remaining = crates
+                .map(|(c, version, _)| format!("{}@{version}", c.name))
+                .collect();
+            return Err(output
+                .map_or_else(|e| e, |output| tool_failed("cargo publish", &output))
+                .context(format!("Failed to publish crate: {crate_version}")));
+        }
+
+        summary.published.push(crate_version);
+    }
+
+    summary.print();
+
+    Ok(())
+}
+
+/// Check that every publishable crate in the workspace can be packaged, without publishing it.
+///
+/// The crates that are not published yet are checked together, with a single
+/// `cargo publish --dry-run`, so that a crate can depend on the new version of another crate in
+/// the workspace before it is in the registry. Crates that are already published are reported,
+/// and not checked.
+pub(crate) fn publish_dry_run(dir: impl AsRef<Path>, status: &[CrateStatus]) -> anyhow::Result<()> {
+    let names = |published: bool| {
+        status
+            .iter()
+            .filter(|(_, _, p)| *p == published)
+            .map(|(c, version, _)| format!("{}@{version}", c.name))
+            .collect::<Vec<_>>()
+    };
+    println!(
+        "[dry-run] Would publish crates in order: {}",
+        list_or_none(&names(false))
+    );
+    println!(
+        "[dry-run] Would skip already published crates: {}",
+        list_or_none(&names(true))
+    );
 
-    if !status.success() {
-        anyhow::bail!("Failed to publish workspace");
+    let unpublished = status
+        .iter()
+        .filter(|(_, _, published)| !published)
+        .map(|(c, _, _)| c.name.as_str())
+        .collect::<Vec<_>>();
+    if unpublished.is_empty() {
+        return Ok(());
+    }
+
+    let mut command = std::process::Command::new("cargo");
+    command.current_dir(&dir).arg("publish").arg("--dry-run");
+    for name in &unpublished {
+        command.arg("--package").arg(name);
+    }
+    let output = run_tool("cargo publish", &mut command)?;
+
+    if !output.status.success() {
+        return Err(tool_failed("cargo publish", &output).context(format!(
+            "Dry run publish failed for crates: {}",
+            unpublished.join(", ")
+        )));
     }
 
     Ok(())
 }
 
-pub(crate) fn create_gh_release(dir: impl AsRef<Path>, tag: &str) -> anyhow::Result<()> {
-    let repository_name = std::env::var("GITHUB_REPOSITORY")
-        .context("Missing environment variable `GITHUB_REPOSITORY`")?
-        .split('/')
-        .next_back()
-        .context("GITHUB_REPOSITORY is not a valid GITHUB_REPOSITORY")?
+/// What to do with a release tag, after checking for an existing tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExistingTag {
+    /// The tag does not exist, or already points to HEAD.
+    Create,
+    /// The tag points to another commit, and is to be moved to HEAD.
+    Move,
+    /// The tag is on an earlier commit, and the crate is already in its registry at the release
+    /// version, so the crate has not changed since it was released.
+    Released,
+}
+
+/// Check whether a release tag can be created on HEAD, locally and on the remote.
+///
+/// A tag that points to another commit may already be the published release, so it is an error
+/// unless `allow_retag` is set. Even then, the tag is not moved if any of the crates in the
+/// release, or the crate called `crate_name`, is already in its registry at the release version.
+///
+/// For an independently versioned crate, called `crate_name`, a tag on an earlier commit is the
+/// previous release when the crate is in its registry at that version, which is not an error.
+///
+/// Whether the crates are in their registry is read from the `status`, from [`publish_status`].
+pub(crate) fn check_existing_tag(
+    repository: &git2::Repository,
+    status: &[CrateStatus],
+    remote: &GitRemote,
+    tag: &str,
+    allow_retag: bool,
+    crate_name: Option<&str>,
+) -> anyhow::Result<ExistingTag> {
+    let head = repository
+        .head()
+        .context("Failed to get HEAD")?
+        .peel_to_commit()
+        .context("Failed to peel HEAD to commit")?
+        .id();
+
+    let local = get_revision_for_tag(repository, tag).ok();
+    let on_remote = remote_tag_revision(repository, remote, tag)?;
+    let elsewhere = [
+        ("locally".to_string(), local),
+        (format!("on remote '{}'", remote.name), on_remote),
+    ]
+    .into_iter()
+    .filter_map(|(place, revision)| {
+        revision
+            .filter(|revision| *revision != head.to_string())
+            .map(|revision| (place, revision))
+    })
+    .collect::<Vec<_>>();
+    if elsewhere.is_empty() {
+        return Ok(ExistingTag::Create);
+    }
+    let places = elsewhere
+        .iter()
+        .map(|(place, revision)| format!("{place} at commit {revision}"))
+        .collect::<Vec<_>>()
+        .join(" and ");
+
+    let published = status
+        .iter()
+        .filter(|(c, _, published)| *published && crate_name.is_none_or(|name| c.name == name))
+        .map(|(c, version, _)| format!("{}@{version}", c.name))
+        .collect::<Vec<_>>();
+
+    if crate_name.is_some() {
+        let earlier = elsewhere.iter().all(|(_, revision)| {
+            git2::Oid::from_str(revision).is_ok_and(|commit| {
+                repository
+                    .graph_descendant_of(head, commit)
+                    .unwrap_or(false)
+            })
+        });
+        if earlier && !published.is_empty() {
+            println!("{tag} was already released, skipping.");
+            return Ok(ExistingTag::Released);
+        }
+    }
+
+    if !allow_retag {
+        anyhow::bail!(ReleaseError::TagConflict {
+            tag: tag.to_string(),
+            message: format!(
+                "Tag '{tag}' already exists {places}, not at HEAD commit {head}. Release tags are not moved, because the release may already be published. Pass `--allow-retag` to move it"
+            ),
+        });
+    }
+
+    if !published.is_empty() {
+        anyhow::bail!(ReleaseError::TagConflict {
+            tag: tag.to_string(),
+            message: format!(
+                "Cannot move tag '{tag}', the release is already in the registry: {}",
+                published.join(", ")
+            ),
+        });
+    }
+
+    println!("Tag '{tag}' exists {places}, it will be moved to HEAD commit {head}");
+
+    Ok(ExistingTag::Move)
+}
+
+/// A publishable crate, with its version and whether that version is already in the crate's
+/// registry.
+pub(crate) type CrateStatus = (WorkspaceCrate, String, bool);
+
+/// List the publishable crates in dependency order, with their [`CrateStatus`].
+///
+/// The registries are queried once, so this is called once for a release, and the result is
+/// shared by the checks and by publishing.
+pub(crate) fn publish_status(dir: impl AsRef<Path>) -> anyhow::Result<Vec<CrateStatus>> {
+    let mut registries = HashMap::<Option<String>, Registry>::new();
+    let mut status = Vec::new();
+    for workspace_crate in workspace_crates(&dir)?.into_iter().filter(|c| c.publish) {
+        let version = workspace_crate
+            .version
+            .clone()
+            .with_context(|| format!("Crate '{}' does not have a version", workspace_crate.name))?;
+
+        let registry = match registries.entry(workspace_crate.registry.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Registry::for_crate(
+                &dir,
+                workspace_crate.registry.as_deref(),
+            )?),
+        };
+        let published = registry
+            .is_published(&workspace_crate.name, &version)
+            .with_context(|| ReleaseError::Registry {
+                message: format!(
+                    "Failed to check whether {}@{version} is published to registry '{}'",
+                    workspace_crate.name,
+                    registry.name()
+                ),
+            })?;
+
+        status.push((workspace_crate, version, published));
+    }
+
+    Ok(status)
+}
+
+/// Report what tagging the current HEAD commit and pushing the tag would do, without doing it.
+pub(crate) fn report_tag(
+    repository: &git2::Repository,
+    remote: &GitRemote,
+    tag: &str,
+) -> anyhow::Result<()> {
+    let head = repository
+        .head()
+        .context("Failed to get HEAD")?
+        .peel_to_commit()
+        .context("Failed to peel HEAD to commit")?
+        .id()
         .to_string();
 
-    let tag_version = tag.trim_start_matches('v');
-
-    std::process::Command::new("gh")
-        .current_dir(dir)
-        .arg("release")
-        .arg("create")
-        .arg(tag)
-        .arg("--generate-notes")
-        .arg("--title")
-        .arg(format!("{} {}", repository_name, tag_version))
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .status()
-        .context("Failed to create GitHub release")?;
+    match get_revision_for_tag(repository, tag) {
+        Ok(revision) if revision == head => {
+            println!("[dry-run] Tag '{tag}' already exists for commit {head}");
+        }
+        Ok(revision) => {
+            println!("[dry-run] Would move tag '{tag}' from commit {revision} to commit {head}");
+        }
+        Err(_) => {
+            println!("[dry-run] Would create tag '{tag}' for commit {head}");
+        }
+    }
+
+    let git_remote = repository
+        .find_remote(&remote.name)
+        .with_context(|| format!("Failed to find remote '{}'", remote.name))?;
+    println!(
+        "[dry-run] Would push '{}' to remote '{}' ({})",
+        tag_refspec(tag),
+        remote.name,
+        git_remote.url().unwrap_or("<invalid url>")
+    );
 
     Ok(())
 }
+
+/// Report the release that would be created for the given tag, without creating it.
+pub(crate) fn report_release(
+    forge: &dyn Forge,
+    tag: &str,
+    version: &str,
+    crate_name: Option<&str>,
+    notes: Option<&str>,
+    assets: &[PathBuf],
+) {
+    println!(
+        "[dry-run] Would create {} '{}' for tag '{tag}' on {} with {}",
+        if is_pre_release(version) {
+            "pre-release"
+        } else {
+            "release"
+        },
+        release_title(forge, version, crate_name),
+        forge.repository(),
+        if notes.is_some() {
+            "notes from the changelog"
+        } else {
+            "generated notes"
+        }
+    );
+    for asset in assets {
+        println!("[dry-run] Would upload {}", asset.display());
+    }
+}
+
+/// Create a release on the forge for the given tag.
+///
+/// The release is titled with the repository name, or with the crate name when releasing a single
+/// crate from a workspace with independent versions, followed by the `version`. The `notes` are
+/// used as the release body, or the forge generates them when there are none. The `assets` are
+/// uploaded to the release once it has been created.
+pub(crate) fn create_release(
+    forge: &dyn Forge,
+    tag: &str,
+    version: &str,
+    crate_name: Option<&str>,
+    notes: Option<String>,
+    assets: &[PathBuf],
+) -> anyhow::Result<()> {
+    let release = forge
+        .create_release(&NewRelease {
+            tag: tag.to_string(),
+            title: release_title(forge, version, crate_name),
+            notes,
+            pre_release: is_pre_release(version),
+        })
+        .with_context(|| ReleaseError::Forge {
+            message: format!("Failed to create the release for tag '{tag}'"),
+        })?;
+    println!("Created release: {}", release.url);
+
+    for asset in assets {
+        forge
+            .upload_release_asset(&release, asset)
+            .with_context(|| ReleaseError::Forge {
+                message: format!("Failed to upload {}", asset.display()),
+            })?;
+        println!("Uploaded {}", asset.display());
+    }
+
+    Ok(())
+}
+
+/// Whether a release version is a pre-release, such as `0.5.0-rc.1`.
+fn is_pre_release(version: &str) -> bool {
+    semver::Version::parse(version).is_ok_and(|version| !version.pre.is_empty())
+}
+
+fn release_title(forge: &dyn Forge, version: &str, crate_name: Option<&str>) -> String {
+    let release_name = crate_name.unwrap_or_else(|| {
+        forge
+            .repository()
+            .rsplit('/')
+            .next()
+            .unwrap_or(forge.repository())
+    });
+
+    format!("{} {}", release_name, version)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::utils::{GitCredentials, push_tag, tag};
+
+    #[test]
+    fn refuse_to_move_release_tags() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        git2::Repository::init_bare(remote_dir.path()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let repository = git2::Repository::init(dir.path()).unwrap();
+        let mut config = repository.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repository
+            .remote("origin", remote_dir.path().to_str().unwrap())
+            .unwrap();
+        let commit = |message: &str| {
+            let signature = repository.signature().unwrap();
+            let tree = repository
+                .find_tree(repository.index().unwrap().write_tree().unwrap())
+                .unwrap();
+            let parents = repository
+                .head()
+                .ok()
+                .map(|head| vec![head.peel_to_commit().unwrap()])
+                .unwrap_or_default();
+            let parents = parents.iter().collect::<Vec<_>>();
+            repository
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+        };
+        let remote = GitRemote::new(GitCredentials::None);
+
+        commit("chore: first");
+        assert_eq!(
+            check_existing_tag(&repository, &[], &remote, "v0.1.0", false, None).unwrap(),
+            ExistingTag::Create
+        );
+        tag(&repository, "v0.1.0", "v0.1.0", None, false).unwrap();
+        push_tag(&repository, &remote, "v0.1.0", false).unwrap();
+        assert_eq!(
+            check_existing_tag(&repository, &[], &remote, "v0.1.0", false, None).unwrap(),
+            ExistingTag::Create
+        );
+
+        commit("fix: second");
+        let err = check_existing_tag(&repository, &[], &remote, "v0.1.0", false, None).unwrap_err();
+        assert!(err.to_string().contains("locally at commit"), "{err}");
+        assert!(err.to_string().contains("on remote 'origin'"), "{err}");
+        assert!(matches!(
+            err.downcast_ref::<ReleaseError>(),
+            Some(ReleaseError::TagConflict { tag, .. }) if tag == "v0.1.0"
+        ));
+        assert!(tag(&repository, "v0.1.0", "v0.1.0", None, false).is_err());
+
+        // The tag is still found on the remote when it is only deleted locally.
+        repository.tag_delete("v0.1.0").unwrap();
+        let err = check_existing_tag(&repository, &[], &remote, "v0.1.0", false, None).unwrap_err();
+        assert!(!err.to_string().contains("locally"), "{err}");
+        assert!(err.to_string().contains("--allow-retag"), "{err}");
+
+        // A crate's tag on an earlier commit is its previous release once the crate is in the
+        // registry, and is refused until then.
+        let index = serve_index("{\"name\":\"a\",\"vers\":\"0.1.0\"}\n");
+        std::fs::create_dir_all(dir.path().join(".cargo")).unwrap();
+        std::fs::write(
+            dir.path().join(".cargo/config.toml"),
+            format!("[registries.local]\nindex = \"sparse+{index}\"\n"),
+        )
+        .unwrap();
+        let write_crate = |version: &str| {
+            std::fs::write(
+                dir.path().join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"a\"\nversion = \"{version}\"\npublish = [\"local\"]\n"
+                ),
+            )
+            .unwrap();
+        };
+        write_crate("0.1.0");
+        tag(&repository, "a-v0.1.0", "a-v0.1.0", None, false).unwrap();
+        commit("fix: third");
+        assert_eq!(
+            check_existing_tag(
+                &repository,
+                &publish_status(dir.path()).unwrap(),
+                &remote,
+                "a-v0.1.0",
+                false,
+                Some("a")
+            )
+            .unwrap(),
+            ExistingTag::Released
+        );
+
+        write_crate("0.2.0");
+        tag(&repository, "a-v0.2.0", "a-v0.2.0", None, false).unwrap();
+        commit("fix: fourth");
+        let status = publish_status(dir.path()).unwrap();
+        let err = check_existing_tag(&repository, &status, &remote, "a-v0.2.0", false, Some("a"))
+            .unwrap_err();
+        assert!(err.to_string().contains("--allow-retag"), "{err}");
+        assert_eq!(
+            check_existing_tag(&repository, &status, &remote, "a-v0.2.0", true, Some("a")).unwrap(),
+            ExistingTag::Move
+        );
+    }
+
+    /// Serve a sparse registry index that answers every request with the same `entries`, and
+    /// return its URL.
+    pub(crate) fn serve_index(entries: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match std::io::Read::read(&mut stream, &mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{entries}",
+                    entries.len()
+                );
+                let _ = std::io::Write::write_all(&mut stream, response.as_bytes());
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn check_versions_before_tagging() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\"]\n\n[workspace.package]\nversion = \"0.2.0\"\n",
+        )
+        .unwrap();
+        for (name, version) in [
+            ("a", "version.workspace = true"),
+            ("b", "version = \"0.1.0\""),
+        ] {
+            std::fs::create_dir_all(dir.path().join(name).join("src")).unwrap();
+            std::fs::write(
+                dir.path().join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\n{version}\n"),
+            )
+            .unwrap();
+            std::fs::write(dir.path().join(name).join("src/lib.rs"), "").unwrap();
+        }
+        std::fs::write(
+            dir.path().join("CHANGELOG.md"),
+            "# Changelog\n\n## \\[[0.2.0](https://example.com/compare/v0.1.0...v0.2.0)\\] - 2025-01-01\n\n## \\[0.1.0\\]\n",
+        )
+        .unwrap();
+
+        let err = check_release_versions(dir.path(), "0.2.0").unwrap_err();
+        assert!(err.to_string().contains("b 0.1.0"), "{err}");
+
+        std::fs::write(
+            dir.path().join("b/Cargo.toml"),
+            "[package]\nname = \"b\"\nversion.workspace = true\n",
+        )
+        .unwrap();
+        check_release_versions(dir.path(), "0.2.0").unwrap();
+
+        let err = check_release_versions(dir.path(), "0.3.0").unwrap_err();
+        assert!(err.to_string().contains("crates must be"), "{err}");
+    }
+
+    #[test]
+    fn find_latest_changelog_version() {
+        assert_eq!(
+            latest_changelog_version(
+                "# Changelog\n\n## [0.2.0-dev.1](url/v0.1.0...v0.2.0-dev.1)\n"
+            ),
+            Some("0.2.0-dev.1")
+        );
+        assert_eq!(
+            latest_changelog_version("# Changelog\n\n## [Unreleased]\n"),
+            None
+        );
+        assert_eq!(latest_changelog_version("# Changelog\n"), None);
+    }
+
+    #[test]
+    fn extract_release_notes_from_changelog() {
+        let changelog = "# Changelog\n\n## [0.2.0](url/v0.1.0...v0.2.0) - 2025-02-01\n\n### Features\n\n- Add f\n\n## [0.2.0-rc.0] - 2025-01-15\n\n- Pre-release\n\n## [0.1.0] - 2025-01-01\n\n### Fixes\n\n- Fix g\n";
+
+        assert_eq!(
+            changelog_section(changelog, "0.2.0").as_deref(),
+            Some("### Features\n\n- Add f")
+        );
+        assert_eq!(
+            changelog_section(changelog, "0.2.0-rc.0").as_deref(),
+            Some("- Pre-release")
+        );
+        assert_eq!(
+            changelog_section(changelog, "0.1.0").as_deref(),
+            Some("### Fixes\n\n- Fix g")
+        );
+        assert_eq!(changelog_section(changelog, "0.3.0"), None);
+
+        // Releases for pre-release versions are marked as pre-releases.
+        assert!(is_pre_release("0.2.0-rc.0"));
+        assert!(!is_pre_release("0.2.0"));
+        assert_eq!(
+            changelog_section("# Changelog\n\n## [0.1.0]\n", "0.1.0"),
+            None
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Lookups against the sparse index of the registry that crates are published to.
+//!
+//! The registry for a crate is found the same way Cargo finds it: the registry named in the
+//! crate's `publish` field, otherwise the default registry from the Cargo configuration, otherwise
+//! crates.io.
+
+use anyhow::Context;
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue, USER_AGENT};
+use std::path::{Path, PathBuf};
+
+const CRATES_IO: &str = "crates-io";
+const CRATES_IO_INDEX: &str = "sparse+https://index.crates.io/";
+
+/// A registry with a sparse index.
+pub(crate) struct Registry {
+    name: String,
+    index_url: String,
+    has_token: bool,
+    client: Client,
+}
+
+impl Registry {
+    /// Find the registry that a crate in `dir` is published to.
+    ///
+    /// The `registry` is the registry named in the crate's `publish` field, if any.
+    pub(crate) fn for_crate(dir: impl AsRef<Path>, registry: Option<&str>) -> anyhow::Result<Self> {
+        let config = CargoConfig::load(dir)?;
+
+        let name = match registry {
+            Some(registry) => registry.to_string(),
+            None => std::env::var("CARGO_REGISTRY_DEFAULT")
+                .ok()
+                .or_else(|| config.get_str(&["registry", "default"]))
+                .unwrap_or_else(|| CRATES_IO.to_string()),
+        };
+
+        let env_prefix = format!("CARGO_REGISTRIES_{}", name.to_uppercase().replace('-', "_"));
+        let index = if name == CRATES_IO {
+            CRATES_IO_INDEX.to_string()
+        } else {
+            std::env::var(format!("{env_prefix}_INDEX"))
+                .ok()
+                .or_else(|| config.get_str(&["registries", &name, "index"]))
+                .with_context(|| format!("No index configured for registry '{name}'"))?
+        };
+
+        let index_url = index
+            .strip_prefix("sparse+")
+            .with_context(|| {
+                format!("Registry '{name}' does not use a sparse index, which is required: {index}")
+            })?
+            .trim_end_matches('/')
+            .to_string();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_static("holochain_release_util"),
+        );
+        let token = if name == CRATES_IO {
+            std::env::var("CARGO_REGISTRY_TOKEN")
+                .ok()
+                .or_else(|| config.get_str(&["registry", "token"]))
+        } else {
+            std::env::var(format!("{env_prefix}_TOKEN"))
+                .ok()
+                .or_else(|| config.get_str(&["registries", &name, "token"]))
+        };
+        let has_token = token.is_some();
+        // Private registries that require authentication also require it to read the index. The
+        // crates.io index is public, so its token is not sent.
+        if let Some(token) = token.filter(|_| name != CRATES_IO) {
+            let mut authorization = HeaderValue::from_str(&token)
+                .with_context(|| format!("Invalid token for registry '{name}'"))?;
+            authorization.set_sensitive(true);
+            headers.insert(AUTHORIZATION, authorization);
+        }
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .build()
+            .context("Failed to create registry client")?;
+
+        Ok(Registry {
+            name,
+            index_url,
+            has_token,
+            client,
+        })
+    }
+
+    /// The name of the registry.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether a token is configured for the registry, which is needed to publish to it.
+    pub(crate) fn has_token(&self) -> bool {
+        self.has_token
+    }
+
+    /// Check whether the given version of a crate is already in the registry's index.
+    pub(crate) fn is_published(&self, crate_name: &str, version: &str) -> anyhow::Result<bool> {
+        let url = format!("{}/{}", self.index_url, index_path(crate_name));
+        let response =
+            self.client.get(&url).send().with_context(|| {
+                format!("Failed to query the index of registry '{}'", self.name)
+            })?;
+
+        // Registries respond with either of these when the crate has never been published.
+        if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+            return Ok(false);
+        }
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "Index request to {url} failed with status {}",
+                response.status()
+            );
+        }
+
+        let entries = response.text().context("Failed to read index entry")?;
+        for line in entries.lines().filter(|line| !line.trim().is_empty()) {
+            let entry = serde_json::from_str::<serde_json::Value>(line)
+                .context("Invalid entry in registry index")?;
+            if entry.get("vers").and_then(|v| v.as_str()) == Some(version) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// The path of a crate's file in a registry index.
+///
+/// See the [Cargo documentation](https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files).
+fn index_path(crate_name: &str) -> String {
+    let name = crate_name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+/// The Cargo configuration and credential files that apply to a directory, nearest first.
+struct CargoConfig {
+    tables: Vec<toml::Table>,
+}
+
+impl CargoConfig {
+    fn load(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir
+            .as_ref()
+            .canonicalize()
+            .context("Failed to resolve directory")?;
+
+        let mut paths = Vec::new();
+        for ancestor in dir.ancestors() {
+            paths.push(ancestor.join(".cargo").join("config.toml"));
+            paths.push(ancestor.join(".cargo").join("config"));
+        }
+        if let Some(cargo_home) = cargo_home() {
+            paths.push(cargo_home.join("config.toml"));
+            paths.push(cargo_home.join("config"));
+            // Written by `cargo login`, with the same layout as the configuration.
+            paths.push(cargo_home.join("credentials.toml"));
+            paths.push(cargo_home.join("credentials"));
+        }
+
+        let mut tables = Vec::new();
+        for path in paths.into_iter().filter(|p| p.is_file()) {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            tables.push(
+                toml::from_str(&content)
+                    .with_context(|| format!("Invalid Cargo configuration: {}", path.display()))?,
+            );
+        }
+
+        Ok(CargoConfig { tables })
+    }
+
+    /// Get a string value, from the nearest configuration file that sets it.
+    fn get_str(&self, keys: &[&str]) -> Option<String> {
+        self.tables.iter().find_map(|table| {
+            let (last, parents) = keys.split_last()?;
+            let mut current = table;
+            for key in parents {
+                current = current.get(*key)?.as_table()?;
+            }
+            current.get(*last)?.as_str().map(ToString::to_string)
+        })
+    }
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_index_paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("Serde_Json"), "se/rd/serde_json");
+    }
+
+    #[test]
+    fn find_registry_from_cargo_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".cargo")).unwrap();
+        std::fs::write(
+            dir.path().join(".cargo").join("config.toml"),
+            r#"
+[registries.dev-registry]
+index = "sparse+http://localhost:8000/api/v1/crates/"
+
+[registries.git-registry]
+index = "https://example.com/index.git"
+
+[registry]
+default = "dev-registry"
+"#,
+        )
+        .unwrap();
+
+        let registry = Registry::for_crate(dir.path(), None).unwrap();
+        assert_eq!(registry.name(), "dev-registry");
+        assert_eq!(registry.index_url, "http://localhost:8000/api/v1/crates");
+
+        assert!(Registry::for_crate(dir.path(), Some("git-registry")).is_err());
+        assert!(Registry::for_crate(dir.path(), Some("missing")).is_err());
+    }
+}
@@ -0,0 +1,497 @@
+//! Sign release tags with GPG or SSH, and verify the signatures on tags.
+//!
+//! Signatures are made and checked in the same way as `git tag -s` and `git tag -v`, by running
+//! `gpg` or `ssh-keygen`, so tags signed here can be verified with git and the other way around.
+
+use crate::error::{ReleaseError, into_release_error, tool_error, tool_failed};
+use anyhow::Context;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The environment variable that can hold the private signing key, for use in CI.
+///
+/// This is an SSH private key, or an ASCII armored GPG secret key, and takes precedence over the
+/// key in the configuration.
+pub const SIGNING_KEY_ENV: &str = "RELEASE_UTIL_SIGNING_KEY";
+
+const GPG_SIGNATURE_START: &str = "-----BEGIN PGP SIGNATURE-----";
+const SSH_SIGNATURE_START: &str = "-----BEGIN SSH SIGNATURE-----";
+
+/// The kind of signature on a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    /// An OpenPGP signature, made with `gpg`.
+    Gpg,
+    /// An SSH signature, made with `ssh-keygen -Y sign`.
+    Ssh,
+}
+
+/// The settings for signing release tags, and for verifying them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SigningConfig {
+    /// The kind of signature to make.
+    pub format: SigningFormat,
+    /// The key to sign with.
+    ///
+    /// For GPG, this is the ID or fingerprint of a key in the keyring, and the default key is used
+    /// if it is not set. For SSH, this is the path to the private key, relative to the repository.
+    pub key: Option<String>,
+    /// A file that contains the private key, relative to the repository.
+    ///
+    /// For GPG, this is an ASCII armored secret key, which is imported into the keyring. It takes
+    /// precedence over `key`.
+    pub key_file: Option<String>,
+    /// The allowed signers file that SSH signatures are verified against, relative to the
+    /// repository, in the format described in `ssh-keygen(1)`.
+    ///
+    /// Without it, a tag signed here is verified against the public key of the signing key.
+    pub allowed_signers: Option<String>,
+}
+
+/// Signs release tags with a resolved key.
+#[derive(Debug)]
+pub struct TagSigner {
+    format: SigningFormat,
+    /// The GPG key ID, or the path to the SSH private key.
+    key: Option<String>,
+    verifier: TagVerifier,
+    /// Keeps the temporary key and allowed signers files until the signer is dropped.
+    _temp_files: Vec<tempfile::NamedTempFile>,
+}
+
+/// Who to trust when verifying the signature on a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagVerifier {
+    /// Trust GPG signatures that the keyring can verify, only from the key with this ID or
+    /// fingerprint if it is set.
+    Gpg { key: Option<String> },
+    /// Trust SSH signatures from the keys in an allowed signers file, for the tagger's email.
+    Ssh { allowed_signers: PathBuf },
+}
+
+impl TagVerifier {
+    /// The verifier for tags signed with the configured key, for checking a tag without the
+    /// private key.
+    ///
+    /// SSH signatures need `allowed-signers`, and GPG signatures are checked against `key` if it
+    /// is set.
+    pub fn from_config(
+        dir: impl AsRef<Path>,
+        config: &SigningConfig,
+    ) -> Result<Self, ReleaseError> {
+        match config.format {
+            SigningFormat::Gpg => Ok(TagVerifier::Gpg {
+                key: config.key.clone(),
+            }),
+            SigningFormat::Ssh => {
+                let Some(allowed_signers) = &config.allowed_signers else {
+                    return Err(ReleaseError::Signing {
+                        message: "Verifying SSH signatures needs `allowed-signers` in the signing configuration".to_string(),
+                    });
+                };
+
+                Ok(TagVerifier::Ssh {
+                    allowed_signers: dir.as_ref().join(allowed_signers),
+                })
+            }
+        }
+    }
+}
+
+impl TagSigner {
+    /// Resolve the signing key for the repository in `dir`.
+    ///
+    /// The key is taken from the [`SIGNING_KEY_ENV`] environment variable if it is set, then from
+    /// `key-file`, and then from `key`.
+    pub fn from_config(
+        dir: impl AsRef<Path>,
+        config: &SigningConfig,
+    ) -> Result<Self, ReleaseError> {
+        Self::resolve(dir.as_ref(), config).map_err(signing_error)
+    }
+
+    fn resolve(dir: &Path, config: &SigningConfig) -> anyhow::Result<Self> {
+        let mut temp_files = Vec::new();
+
+        let key_material = match std::env::var(SIGNING_KEY_ENV)
+            .ok()
+            .filter(|key| !key.trim().is_empty())
+        {
+            Some(key) => Some(key),
+            None => config
+                .key_file
+                .as_ref()
+                .map(|path| {
+                    std::fs::read_to_string(dir.join(path))
+                        .with_context(|| format!("Failed to read signing key file: {path}"))
+                })
+                .transpose()?,
+        };
+
+        let key = match (config.format, key_material) {
+            (SigningFormat::Gpg, Some(key)) => Some(import_gpg_key(&key)?),
+            (SigningFormat::Ssh, Some(key)) => {
+                let mut file =
+                    tempfile::NamedTempFile::new().context("Failed to create a key file")?;
+                writeln!(file, "{}", key.trim()).context("Failed to write the signing key")?;
+                let path = file.path().to_string_lossy().to_string();
+                temp_files.push(file);
+                Some(path)
+            }
+            (SigningFormat::Gpg, None) => config.key.clone(),
+            (SigningFormat::Ssh, None) => config
+                .key
+                .as_ref()
+                .map(|path| dir.join(path).to_string_lossy().to_string()),
+        };
+
+        let verifier = match config.format {
+            SigningFormat::Gpg => TagVerifier::Gpg { key: key.clone() },
+            SigningFormat::Ssh => {
+                let key_path = key.as_deref().with_context(|| {
+                    format!("Signing tags with SSH needs a key, from `key`, `key-file` or {SIGNING_KEY_ENV}")
+                })?;
+                match &config.allowed_signers {
+                    Some(allowed_signers) => TagVerifier::Ssh {
+                        allowed_signers: dir.join(allowed_signers),
+                    },
+                    None => {
+                        let file = own_allowed_signers(key_path)?;
+                        let allowed_signers = file.path().to_path_buf();
+                        temp_files.push(file);
+                        TagVerifier::Ssh { allowed_signers }
+                    }
+                }
+            }
+        };
+
+        Ok(TagSigner {
+            format: config.format,
+            key,
+            verifier,
+            _temp_files: temp_files,
+        })
+    }
+
+    /// Sign the content of a tag object, and return the armored signature.
+    pub(crate) fn sign(&self, payload: &[u8]) -> anyhow::Result<String> {
+        let mut command = match self.format {
+            SigningFormat::Gpg => {
+                let mut command = Command::new("gpg");
+                command.args(["--batch", "--detach-sign", "--armor"]);
+                if let Some(key) = &self.key {
+                    command.args(["--local-user", key]);
+                }
+                command
+            }
+            SigningFormat::Ssh => {
+                let mut command = Command::new("ssh-keygen");
+                command.args(["-Y", "sign", "-n", "git", "-f"]);
+                command.arg(self.key.as_deref().unwrap_or_default());
+                command
+            }
+        };
+
+        let output = run_with_input(&mut command, payload)?;
+        if !output.status.success() {
+            let program = command.get_program().to_string_lossy();
+            return Err(tool_failed(&program, &output).context("Failed to sign the tag"));
+        }
+
+        String::from_utf8(output.stdout).context("The signature is not valid UTF-8")
+    }
+
+    /// The verifier that accepts tags signed by this signer.
+    pub fn verifier(&self) -> &TagVerifier {
+        &self.verifier
+    }
+}
+
+/// Verify the signature on a release tag, and return a description of the signer.
+///
+/// Fails if the tag is not an annotated tag, if it is not signed, or if the signature is not valid
+/// for a signer that the `verifier` trusts.
+pub fn verify_tag(
+    repository: &git2::Repository,
+    tag: &str,
+    verifier: &TagVerifier,
+) -> Result<String, ReleaseError> {
+    verify(repository, tag, verifier).map_err(signing_error)
+}
+
+fn verify(
+    repository: &git2::Repository,
+    tag: &str,
+    verifier: &TagVerifier,
+) -> anyhow::Result<String> {
+    let reference = repository
+        .find_reference(&format!("refs/tags/{tag}"))
+        .with_context(|| format!("Failed to find tag '{tag}'"))?;
+    let tag_object = reference
+        .peel_to_tag()
+        .with_context(|| format!("Tag '{tag}' is not an annotated tag, so it cannot be signed"))?;
+    let odb = repository
+        .odb()
+        .context("Failed to open the object database")?;
+    let object = odb
+        .read(tag_object.id())
+        .with_context(|| format!("Failed to read tag '{tag}'"))?;
+
+    let (payload, signature) =
+        split_signature(object.data()).with_context(|| format!("Tag '{tag}' is not signed"))?;
+
+    let mut signature_file =
+        tempfile::NamedTempFile::new().context("Failed to create a signature file")?;
+    signature_file
+        .write_all(signature)
+        .context("Failed to write the signature file")?;
+
+    match verifier {
+        TagVerifier::Gpg { key } => {
+            if !signature.starts_with(GPG_SIGNATURE_START.as_bytes()) {
+                anyhow::bail!("Tag '{tag}' has an SSH signature, but a GPG signature is expected");
+            }
+            let output = run_with_input(
+                Command::new("gpg")
+                    .args(["--batch", "--status-fd", "1", "--verify"])
+                    .arg(signature_file.path())
+                    .arg("-"),
+                payload,
+            )?;
+            let status = String::from_utf8_lossy(&output.stdout);
+            let fingerprint = status
+                .lines()
+                .find_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+                .and_then(|rest| rest.split_whitespace().next())
+                .filter(|_| output.status.success());
+            let Some(fingerprint) = fingerprint else {
+                anyhow::bail!(
+                    "Tag '{tag}' does not have a valid GPG signature: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            };
+            if let Some(key) = key
+                && !is_gpg_key(fingerprint, key)
+            {
+                anyhow::bail!("Tag '{tag}' is signed by {fingerprint}, not by {key}");
+            }
+
+            Ok(format!("GPG key {fingerprint}"))
+        }
+        TagVerifier::Ssh { allowed_signers } => {
+            if !signature.starts_with(SSH_SIGNATURE_START.as_bytes()) {
+                anyhow::bail!("Tag '{tag}' has a GPG signature, but an SSH signature is expected");
+            }
+            let tagger = tag_object
+                .tagger()
+                .and_then(|tagger| tagger.email().map(ToString::to_string))
+                .with_context(|| format!("Tag '{tag}' has no tagger email"))?;
+            let output = run_with_input(
+                Command::new("ssh-keygen")
+                    .args(["-Y", "verify", "-n", "git", "-f"])
+                    .arg(allowed_signers)
+                    .arg("-I")
+                    .arg(&tagger)
+                    .arg("-s")
+                    .arg(signature_file.path()),
+                payload,
+            )?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "Tag '{tag}' does not have a valid SSH signature from {tagger}: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+    }
+}
+
+fn signing_error(error: anyhow::Error) -> ReleaseError {
+    into_release_error(error, |message| ReleaseError::Signing { message })
+}
+
+/// Build the content of an annotated tag object, as git writes it, ready to be signed.
+pub(crate) fn tag_payload(
+    target: git2::Oid,
+    name: &str,
+    tagger: &git2::Signature,
+    message: &str,
+) -> String {
+    let time = tagger.when();
+    let offset = time.offset_minutes();
+    let mut payload = format!(
+        "object {target}\ntype commit\ntag {name}\ntagger {} <{}> {} {}{:02}{:02}\n\n{message}",
+        tagger.name().unwrap_or_default(),
+        tagger.email().unwrap_or_default(),
+        time.seconds(),
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60,
+    );
+    if !payload.ends_with('\n') {
+        payload.push('\n');
+    }
+
+    payload
+}
+
+/// Split a tag object into the signed content and the signature that follows it.
+fn split_signature(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    [GPG_SIGNATURE_START, SSH_SIGNATURE_START]
+        .iter()
+        .filter_map(|start| {
+            let marker = format!("\n{start}");
+            data.windows(marker.len())
+                .rposition(|window| window == marker.as_bytes())
+                .map(|position| data.split_at(position + 1))
+        })
+        .max_by_key(|(payload, _)| payload.len())
+}
+
+/// Whether a GPG fingerprint belongs to the key given as an ID or fingerprint.
+fn is_gpg_key(fingerprint: &str, key: &str) -> bool {
+    let key = key.trim_start_matches("0x").to_ascii_uppercase();
+
+    !key.is_empty() && fingerprint.to_ascii_uppercase().ends_with(&key)
+}
+
+/// Import an ASCII armored secret key into the GPG keyring, and return its fingerprint.
+fn import_gpg_key(key: &str) -> anyhow::Result<String> {
+    let output = run_with_input(
+        Command::new("gpg").args(["--batch", "--status-fd", "1", "--import"]),
+        key.as_bytes(),
+    )?;
+    if !output.status.success() {
+        return Err(tool_failed("gpg", &output).context("Failed to import the GPG signing key"));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] IMPORT_OK "))
+        .find_map(|rest| rest.split_whitespace().nth(1))
+        .map(ToString::to_string)
+        .context("No key was imported from the GPG signing key")
+}
+
+/// Write an allowed signers file that trusts the public key of an SSH private key.
+fn own_allowed_signers(key_path: &str) -> anyhow::Result<tempfile::NamedTempFile> {
+    let output = Command::new("ssh-keygen")
+        .args(["-y", "-f", key_path])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| tool_error("ssh-keygen", e))?;
+    if !output.status.success() {
+        return Err(tool_failed("ssh-keygen", &output)
+            .context(format!("Failed to read the public key of {key_path}")));
+    }
+
+    let mut file =
+        tempfile::NamedTempFile::new().context("Failed to create an allowed signers file")?;
+    writeln!(file, "* {}", String::from_utf8_lossy(&output.stdout).trim())
+        .context("Failed to write the allowed signers file")?;
+
+    Ok(file)
+}
+
+/// Run a command with `input` on its standard input, and capture its output.
+fn run_with_input(command: &mut Command, input: &[u8]) -> anyhow::Result<std::process::Output> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| tool_error(&program, e))?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open stdin")?
+        .write_all(input)
+        .with_context(|| format!("Failed to write to `{program}`"))?;
+
+    child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run `{program}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_signed_tag() {
+        let data = b"object abc\ntype commit\ntag v0.1.0\n\nv0.1.0\n-----BEGIN SSH SIGNATURE-----\nsig\n-----END SSH SIGNATURE-----\n";
+        let (payload, signature) = split_signature(data).unwrap();
+        assert_eq!(payload, b"object abc\ntype commit\ntag v0.1.0\n\nv0.1.0\n");
+        assert!(signature.starts_with(SSH_SIGNATURE_START.as_bytes()));
+
+        assert!(split_signature(b"object abc\n\nunsigned\n").is_none());
+        assert!(is_gpg_key("0123456789ABCDEF", "0x89abcdef"));
+        assert!(!is_gpg_key("0123456789ABCDEF", "01234567"));
+    }
+
+    #[test]
+    fn sign_and_verify_with_ssh() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = git2::Repository::init(dir.path()).unwrap();
+        let mut config = repository.config().unwrap();
+        config.set_str("user.name", "release bot").unwrap();
+        config.set_str("user.email", "bot@example.com").unwrap();
+        let signature = repository.signature().unwrap();
+        let tree = repository
+            .find_tree(repository.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repository
+            .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        let generate_key = |name: &str| {
+            let status = Command::new("ssh-keygen")
+                .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+                .arg(dir.path().join(name))
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        generate_key("release_key");
+        generate_key("other_key");
+
+        let signer = TagSigner::from_config(
+            dir.path(),
+            &SigningConfig {
+                format: SigningFormat::Ssh,
+                key: Some("release_key".to_string()),
+                key_file: None,
+                allowed_signers: None,
+            },
+        )
+        .unwrap();
+        crate::utils::tag(&repository, "v0.1.0", "v0.1.0", Some(&signer), false).unwrap();
+        crate::utils::tag(&repository, "v0.2.0", "v0.2.0", None, false).unwrap();
+
+        let signed_by = verify_tag(&repository, "v0.1.0", signer.verifier()).unwrap();
+        assert!(signed_by.contains("bot@example.com"), "{signed_by}");
+
+        let err = verify_tag(&repository, "v0.2.0", signer.verifier()).unwrap_err();
+        assert!(err.to_string().contains("not signed"), "{err}");
+
+        // A signature from a key that is not trusted is rejected.
+        let other_public_key = std::fs::read_to_string(dir.path().join("other_key.pub")).unwrap();
+        std::fs::write(
+            dir.path().join("allowed_signers"),
+            format!("bot@example.com {other_public_key}"),
+        )
+        .unwrap();
+        let verifier = TagVerifier::Ssh {
+            allowed_signers: dir.path().join("allowed_signers"),
+        };
+        assert!(verify_tag(&repository, "v0.1.0", &verifier).is_err());
+        assert!(verify_tag(&repository, "v0.1.0", &TagVerifier::Gpg { key: None }).is_err());
+    }
+}
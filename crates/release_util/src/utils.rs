@@ -2,11 +2,19 @@
 //!
 //! This module contains code that is common between this crate and the integration test crate.
 
+use crate::error::ReleaseError;
+use crate::manifest::workspace_crates;
+use crate::signing::{TagSigner, tag_payload};
 use anyhow::Context;
 use git2::{ObjectType, RemoteCallbacks};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// Get the Git revision for a given tag in a repository.
-pub fn get_revision_for_tag(repository: &git2::Repository, tag: &str) -> anyhow::Result<String> {
+pub fn get_revision_for_tag(
+    repository: &git2::Repository,
+    tag: &str,
+) -> Result<String, ReleaseError> {
     let id = repository
         .revparse_single(format!("refs/tags/{}", tag).as_str())
         .context("Failed to find tag")?
@@ -17,13 +25,46 @@ pub fn get_revision_for_tag(repository: &git2::Repository, tag: &str) -> anyhow:
     Ok(id.to_string())
 }
 
+/// Get the name of the branch that is checked out.
+///
+/// When HEAD is detached, as it can be in CI, the `GITHUB_REF_NAME` environment variable is used
+/// instead.
+pub fn current_branch(repository: &git2::Repository) -> Result<String, ReleaseError> {
+    let head = repository.head().context("Failed to get HEAD")?;
+    if head.is_branch()
+        && let Some(name) = head.shorthand()
+    {
+        return Ok(name.to_string());
+    }
+
+    Ok(std::env::var("GITHUB_REF_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .context(
+            "HEAD is detached and GITHUB_REF_NAME is not set, cannot find the current branch",
+        )?)
+}
+
+/// Get the refspec that is used to push a tag to the remote repository.
+pub fn tag_refspec(tag: &str) -> String {
+    format!("refs/tags/{tag}:refs/tags/{tag}")
+}
+
 /// Create a tag in the given repository.
 ///
 /// - If the tag exists and already points to the current HEAD commit, it will not be created again.
-/// - If the tag exists but points to a different commit, it will be updated to point to the current
-///   HEAD commit.
+/// - If the tag exists but points to a different commit, it is an error, because a release tag may
+///   already have been published. With `allow_retag`, the tag is moved to the current HEAD commit.
 /// - If the tag does not exist, it will be created pointing to the current HEAD commit.
-pub fn tag(repository: &git2::Repository, tag: &str, message: &str) -> anyhow::Result<()> {
+///
+/// With a `signer`, the tag is signed in the same way as `git tag -s`.
+pub fn tag(
+    repository: &git2::Repository,
+    tag: &str,
+    message: &str,
+    signer: Option<&TagSigner>,
+    allow_retag: bool,
+) -> Result<(), ReleaseError> {
     let signature = repository.signature().context("Failed to get signature")?;
     let head = repository.head().context("Failed to get HEAD")?;
     let commit = head
@@ -35,13 +76,22 @@ pub fn tag(repository: &git2::Repository, tag: &str, message: &str) -> anyhow::R
             if commit.id().to_string() == revision {
                 println!("Tag '{}' already exists for commit {}", tag, commit.id());
                 return Ok(());
-            } else {
+            } else if allow_retag {
                 println!(
-                    "Updating existing tag '{}' to point to commit {}",
+                    "Moving existing tag '{}' from commit {} to commit {}",
                     tag,
+                    revision,
                     commit.id()
                 );
                 true
+            } else {
+                return Err(ReleaseError::TagConflict {
+                    tag: tag.to_string(),
+                    message: format!(
+                        "Tag '{tag}' already exists for commit {revision}, refusing to move it to commit {}",
+                        commit.id()
+                    ),
+                });
             }
         }
         Err(_) => {
@@ -51,109 +101,496 @@ pub fn tag(repository: &git2::Repository, tag: &str, message: &str) -> anyhow::R
         }
     };
 
-    repository
-        .tag(tag, &commit, &signature, message, force)
-        .context("Failed to create tag")?;
+    match signer {
+        Some(signer) => {
+            let payload = tag_payload(commit.id(), tag, &signature, message);
+            let signed = payload.clone() + &signer.sign(payload.as_bytes())?;
+            let id = repository
+                .odb()
+                .context("Failed to open the object database")?
+                .write(ObjectType::Tag, signed.as_bytes())
+                .context("Failed to write signed tag")?;
+            repository
+                .reference(
+                    &format!("refs/tags/{tag}"),
+                    id,
+                    force,
+                    "release: signed tag",
+                )
+                .context("Failed to create tag")?;
+        }
+        None => {
+            repository
+                .tag(tag, &commit, &signature, message, force)
+                .context("Failed to create tag")?;
+        }
+    }
 
     Ok(())
 }
 
+/// How to authenticate to the remote when pushing release tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitCredentials {
+    /// A token, sent as the password over HTTPS.
+    Token(String),
+    /// The keys that a running SSH agent holds.
+    SshAgent,
+    /// An SSH private key file, and its passphrase if it has one.
+    SshKey {
+        private_key: PathBuf,
+        passphrase: Option<String>,
+    },
+    /// The credential helpers that are configured for git, as `git push` would use.
+    CredentialHelper,
+    /// No credentials, for remotes that do not ask for any, such as `file://` URLs.
+    None,
+}
+
+/// The remote that release tags are pushed to, and how to authenticate to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRemote {
+    /// The name of the remote in the repository.
+    pub name: String,
+    /// The user name to authenticate as.
+    ///
+    /// When it is not set, the user name in the remote URL is used. For a token, the user's email
+    /// from the git configuration is used instead, and for SSH, `git`.
+    pub username: Option<String>,
+    /// The credentials to authenticate with.
+    pub credentials: GitCredentials,
+}
+
+impl GitRemote {
+    /// Push to `origin` with the given credentials.
+    pub fn new(credentials: GitCredentials) -> Self {
+        GitRemote {
+            name: "origin".to_string(),
+            username: None,
+            credentials,
+        }
+    }
+
+    /// Push to `origin` with a token.
+    pub fn with_token(token: impl Into<String>) -> Self {
+        GitRemote::new(GitCredentials::Token(token.into()))
+    }
+
+    fn callbacks<'a>(&'a self, repository: &git2::Repository) -> RemoteCallbacks<'a> {
+        let config = repository.config().ok();
+        let mut attempted = false;
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            let username = self.username.as_deref().or(username_from_url);
+
+            // For an SSH URL without a user name, libgit2 asks for the user name before the key.
+            if allowed_types.contains(git2::CredentialType::USERNAME) {
+                return git2::Cred::username(username.unwrap_or("git"));
+            }
+
+            // libgit2 asks again when credentials are rejected, so stop rather than loop.
+            if std::mem::replace(&mut attempted, true) {
+                return Err(git2::Error::from_str(&format!(
+                    "The credentials for remote '{}' were rejected",
+                    self.name
+                )));
+            }
+
+            let require = |cred_type: git2::CredentialType, what: &str| {
+                if allowed_types.contains(cred_type) {
+                    Ok(())
+                } else {
+                    Err(git2::Error::from_str(&format!(
+                        "Remote '{}' does not accept {what}, which are the configured credentials",
+                        self.name
+                    )))
+                }
+            };
+
+            match &self.credentials {
+                GitCredentials::Token(token) => {
+                    require(git2::CredentialType::USER_PASS_PLAINTEXT, "tokens")?;
+                    let username = match username {
+                        Some(username) => username.to_string(),
+                        None => user_email(config.as_ref())
+                            .map_err(|e| git2::Error::from_str(&format!("{e:#}")))?,
+                    };
+                    git2::Cred::userpass_plaintext(&username, token)
+                }
+                GitCredentials::SshAgent => {
+                    require(git2::CredentialType::SSH_KEY, "SSH keys")?;
+                    git2::Cred::ssh_key_from_agent(username.unwrap_or("git"))
+                }
+                GitCredentials::SshKey {
+                    private_key,
+                    passphrase,
+                } => {
+                    require(git2::CredentialType::SSH_KEY, "SSH keys")?;
+                    git2::Cred::ssh_key(
+                        username.unwrap_or("git"),
+                        None,
+                        private_key,
+                        passphrase.as_deref(),
+                    )
+                }
+                GitCredentials::CredentialHelper => {
+                    let config = config.as_ref().ok_or_else(|| {
+                        git2::Error::from_str("Failed to read the git configuration")
+                    })?;
+                    git2::Cred::credential_helper(config, url, username)
+                }
+                GitCredentials::None => Err(git2::Error::from_str(&format!(
+                    "Remote '{}' asked for credentials, but none are configured",
+                    self.name
+                ))),
+            }
+        });
+
+        callbacks
+    }
+}
+
+/// Find the user's email in the repository's git configuration, which includes the global one.
+fn user_email(config: Option<&git2::Config>) -> anyhow::Result<String> {
+    match config {
+        Some(config) => config.get_string("user.email"),
+        None => git2::Config::open_default()?.get_string("user.email"),
+    }
+    .context("Failed to get user email, to use as the user name for the token")
+}
+
 /// Push a tag to the remote repository.
 ///
-/// The user's email will be discovered from the repository's configuration or the global Git
-/// configuration. The token used to push must be provided as an argument.
-pub fn push_tag(repository: &git2::Repository, token: &str, tag: &str) -> anyhow::Result<()> {
-    println!("Pushing tag '{}' to remote", tag);
-
-    let email_from_repo = |repository: &git2::Repository| -> anyhow::Result<String> {
-        let config = repository
-            .config()
-            .context("Failed to get repository config")?;
-        let email = config
-            .get_string("user.email")
-            .context("Failed to get user email")?;
-        Ok(email)
+/// With `force`, the tag replaces a tag of the same name on the remote that points elsewhere.
+pub fn push_tag(
+    repository: &git2::Repository,
+    remote: &GitRemote,
+    tag: &str,
+    force: bool,
+) -> Result<(), ReleaseError> {
+    println!("Pushing tag '{}' to remote '{}'", tag, remote.name);
+
+    let refspec = if force {
+        format!("+{}", tag_refspec(tag))
+    } else {
+        tag_refspec(tag)
     };
-    let email_from_global = || -> anyhow::Result<String> {
-        let config = git2::Config::open_default()?;
-        let email = config
-            .get_string("user.email")
-            .context("Failed to get global user email")?;
-        Ok(email)
+    push_refspec(repository, remote, &refspec).context("Failed to push tag to remote")?;
+
+    Ok(())
+}
+
+/// Get the commit that a tag points to on the remote repository, if the remote has the tag.
+///
+/// The tag is fetched into a scratch ref, which is removed again, so the local tags are unchanged.
+pub fn remote_tag_revision(
+    repository: &git2::Repository,
+    remote: &GitRemote,
+    tag: &str,
+) -> Result<Option<String>, ReleaseError> {
+    let mut git_remote = repository
+        .find_remote(&remote.name)
+        .with_context(|| format!("Failed to find remote '{}'", remote.name))?;
+
+    let scratch = format!("refs/release-util/remote-tags/{tag}");
+    let mut fetch_opts = git2::FetchOptions::new();
+    fetch_opts
+        .remote_callbacks(remote.callbacks(repository))
+        .download_tags(git2::AutotagOption::None);
+    git_remote
+        .fetch(
+            &[format!("+refs/tags/{tag}:{scratch}")],
+            Some(&mut fetch_opts),
+            None,
+        )
+        .with_context(|| format!("Failed to fetch tag '{tag}' from remote '{}'", remote.name))?;
+
+    let Ok(mut reference) = repository.find_reference(&scratch) else {
+        return Ok(None);
     };
-    let email = email_from_repo(repository)
-        .or_else(|_| email_from_global())
-        .context("Failed to get user email")?;
+    let revision = reference
+        .peel_to_commit()
+        .map(|commit| commit.id().to_string())
+        .with_context(|| format!("Tag '{tag}' on remote '{}' is not a commit", remote.name));
+    reference
+        .delete()
+        .context("Failed to remove the fetched tag")?;
+
+    Ok(Some(revision?))
+}
+
+/// Delete a tag from the repository and from the remote repository.
+///
+/// It is not an error if the tag does not exist locally.
+pub fn delete_tag(
+    repository: &git2::Repository,
+    remote: &GitRemote,
+    tag: &str,
+) -> Result<(), ReleaseError> {
+    println!("Deleting tag '{}' from remote '{}'", tag, remote.name);
+
+    push_refspec(repository, remote, &format!(":refs/tags/{tag}"))
+        .context("Failed to delete tag from remote")?;
+
+    if repository
+        .find_reference(&format!("refs/tags/{tag}"))
+        .is_ok()
+    {
+        repository
+            .tag_delete(tag)
+            .context("Failed to delete local tag")?;
+    }
+
+    Ok(())
+}
 
-    let mut remote = repository
-        .find_remote("origin")
-        .context("Failed to find remote 'origin'")?;
+fn push_refspec(
+    repository: &git2::Repository,
+    remote: &GitRemote,
+    refspec: &str,
+) -> anyhow::Result<()> {
+    let mut git_remote = repository
+        .find_remote(&remote.name)
+        .with_context(|| format!("Failed to find remote '{}'", remote.name))?;
 
     let mut push_opts = git2::PushOptions::new();
+    push_opts.remote_callbacks(remote.callbacks(repository));
 
-    let mut cb = RemoteCallbacks::new();
-    cb.credentials(|_url, _username, _allowed_types| {
-        let created = git2::Cred::userpass_plaintext(&email, token)?;
-        Ok(created)
-    });
-    push_opts.remote_callbacks(cb);
-
-    remote
-        .push(
-            &[format!("refs/tags/{tag}:refs/tags/{tag}")],
-            Some(&mut push_opts),
-        )
-        .context("Failed to push tag to remote")?;
+    git_remote
+        .push(&[refspec], Some(&mut push_opts))
+        .context("Failed to push to remote")?;
 
     Ok(())
 }
 
-/// Get the version from the output of `git-cliff` command.
+/// Get the current version from the given content.
+///
+/// The content is expected to be the content of a root `Cargo.toml` file, which may be:
+/// - A package, with its own `version` or with `version.workspace = true`, and optionally a
+///   `[workspace]` of other crates.
+/// - A virtual workspace, with the version in `[workspace.package]`.
 ///
-/// `git-cliff` must have been called with the `--context` flag.
-pub fn get_version_from_cliff_output(output: &[u8]) -> anyhow::Result<String> {
-    let value = serde_json::from_slice::<Vec<serde_json::Value>>(output)
-        .context("Unexpected output from git-cliff")?;
-
-    Ok(value
-        .first()
-        .context("No value in git-cliff output list")?
-        .as_object()
-        .context("Expected an object in git-cliff output list")?
-        .get("version")
-        .context("Expected 'version' in git-cliff output")?
-        .as_str()
-        .context("Expected a string as the version in git-cliff output")?
-        .to_string())
+/// A virtual workspace without a `[workspace.package]` version is an error, because only its
+/// members carry versions. Use [`get_current_version`] to read the version from the members.
+pub fn get_current_version_from_cargo_toml(content: &str) -> Result<String, ReleaseError> {
+    Ok(cargo_toml_version(content)?.context(
+        "Detected a virtual workspace without a version in [workspace.package], so only its members carry versions. Set the release version in [workspace.package] and inherit it with `version.workspace = true`, or use independent versions",
+    )?)
 }
 
-/// Get the current version from the given content.
+/// Get the current version of the workspace in `dir`.
 ///
-/// The content is expected to be the content of a `Cargo.toml` file.
-pub fn get_current_version_from_cargo_toml(content: &str) -> anyhow::Result<String> {
-    let cargo_toml = toml::from_str::<toml::Value>(content).context("Invalid TOML")?;
-
-    let get_version_from_table = |table: &toml::Value| -> anyhow::Result<String> {
-        Ok(table
-            .as_table()
-            .context("Expected root to be a table")?
-            .get("package")
-            .context("Expected 'package' in Cargo.toml")?
-            .as_table()
-            .context("Expected 'package' to be a table")?
-            .get("version")
-            .context("Expected 'version' in package table")?
-            .as_str()
-            .context("Expected a string as the version in package table")?
-            .to_string())
+/// This is the version from the root `Cargo.toml`, as for [`get_current_version_from_cargo_toml`].
+/// For a virtual workspace without a `[workspace.package]` version, it is the version that every
+/// publishable member has, and it is an error if they do not all have the same version.
+pub fn get_current_version(dir: impl AsRef<Path>) -> Result<String, ReleaseError> {
+    let dir = dir.as_ref();
+    let content =
+        std::fs::read_to_string(dir.join("Cargo.toml")).context("Failed to read Cargo.toml")?;
+    if let Some(version) = cargo_toml_version(&content)? {
+        return Ok(version);
+    }
+
+    let mut versions = BTreeMap::<String, Vec<String>>::new();
+    for workspace_crate in workspace_crates(dir)?.into_iter().filter(|c| c.publish) {
+        let version = workspace_crate
+            .version
+            .unwrap_or_else(|| "(no version)".to_string());
+        versions
+            .entry(version)
+            .or_default()
+            .push(workspace_crate.name);
+    }
+
+    let mut found = versions.keys();
+    match (found.next(), found.next()) {
+        (Some(version), None) if version != "(no version)" => Ok(version.clone()),
+        (None, _) => Err(anyhow::anyhow!(
+            "Detected a virtual workspace without a version in [workspace.package] or any publishable members to take the version from"
+        )
+        .into()),
+        _ => Err(anyhow::anyhow!(
+            "Detected a virtual workspace without a version in [workspace.package], and its publishable members do not share a version: {}. Set the release version in [workspace.package] and inherit it with `version.workspace = true`, or use independent versions",
+            versions
+                .iter()
+                .map(|(version, crates)| format!("{} at {version}", crates.join(", ")))
+                .collect::<Vec<_>>()
+                .join("; ")
+        )
+        .into()),
+    }
+}
+
+/// The version in a root `Cargo.toml`, or `None` for a virtual workspace without a
+/// `[workspace.package]` version.
+fn cargo_toml_version(content: &str) -> anyhow::Result<Option<String>> {
+    let cargo_toml = toml::from_str::<toml::Table>(content).context("Invalid TOML")?;
+
+    let workspace = cargo_toml.get("workspace");
+    let workspace_version = workspace
+        .and_then(|w| w.get("package"))
+        .and_then(|p| p.get("version"));
+    let workspace_version = || -> anyhow::Result<Option<String>> {
+        workspace_version
+            .map(|v| {
+                v.as_str()
+                    .map(ToString::to_string)
+                    .context("Expected a string as the version in [workspace.package]")
+            })
+            .transpose()
     };
 
-    match cargo_toml
-        .as_table()
-        .context("Expected root to be a table")?
-        .get("workspace")
-    {
-        Some(workspace) => get_version_from_table(workspace),
-        None => get_version_from_table(&cargo_toml),
+    match cargo_toml.get("package") {
+        Some(package) => {
+            let layout = if workspace.is_some() {
+                "a package that is also a workspace root"
+            } else {
+                "a single package"
+            };
+            match package.get("version") {
+                Some(toml::Value::String(version)) => Ok(Some(version.clone())),
+                Some(version)
+                    if version.get("workspace").and_then(toml::Value::as_bool) == Some(true) =>
+                {
+                    workspace_version()?.map(Some).with_context(|| {
+                        format!(
+                            "Detected {layout}, whose version is inherited with `version.workspace = true`, but [workspace.package] has no version"
+                        )
+                    })
+                }
+                Some(_) => anyhow::bail!(
+                    "Detected {layout}, but its version is neither a string nor `version.workspace = true`"
+                ),
+                None => anyhow::bail!(
+                    "Detected {layout} without a version, set `version` in [package] or inherit it with `version.workspace = true`"
+                ),
+            }
+        }
+        None if workspace.is_some() => workspace_version(),
+        None => anyhow::bail!("Expected a [package] or a [workspace] in Cargo.toml"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_tag_to_named_remote() {
+        let remote_dir = tempfile::tempdir().unwrap();
+        let upstream = git2::Repository::init_bare(remote_dir.path()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let repository = git2::Repository::init(dir.path()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repository
+            .find_tree(repository.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let commit = repository
+            .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        repository
+            .tag_lightweight(
+                "v0.1.0",
+                &repository.find_object(commit, None).unwrap(),
+                false,
+            )
+            .unwrap();
+        repository
+            .remote("upstream", remote_dir.path().to_str().unwrap())
+            .unwrap();
+
+        // There is no `origin` remote to push to.
+        let origin = GitRemote::new(GitCredentials::None);
+        assert!(push_tag(&repository, &origin, "v0.1.0", false).is_err());
+
+        let remote = GitRemote {
+            name: "upstream".to_string(),
+            username: Some("release-bot".to_string()),
+            credentials: GitCredentials::None,
+        };
+        assert_eq!(
+            remote_tag_revision(&repository, &remote, "v0.1.0").unwrap(),
+            None
+        );
+        push_tag(&repository, &remote, "v0.1.0", false).unwrap();
+        assert!(upstream.find_reference("refs/tags/v0.1.0").is_ok());
+        assert_eq!(
+            remote_tag_revision(&repository, &remote, "v0.1.0").unwrap(),
+            Some(commit.to_string())
+        );
+
+        delete_tag(&repository, &remote, "v0.1.0").unwrap();
+        assert!(upstream.find_reference("refs/tags/v0.1.0").is_err());
+        assert!(repository.find_reference("refs/tags/v0.1.0").is_err());
+    }
+
+    #[test]
+    fn current_version_for_each_layout() {
+        let version = |content: &str| get_current_version_from_cargo_toml(content);
+
+        // A single package.
+        assert_eq!(
+            version("[package]\nname = \"a\"\nversion = \"0.1.0\"\n").unwrap(),
+            "0.1.0"
+        );
+        // A virtual workspace with an inherited version.
+        assert_eq!(
+            version("[workspace]\nmembers = [\"a\"]\n\n[workspace.package]\nversion = \"0.2.0\"\n")
+                .unwrap(),
+            "0.2.0"
+        );
+        // A root package with a workspace of helper crates, with its own version or the workspace's.
+        assert_eq!(
+            version("[package]\nname = \"a\"\nversion = \"0.3.0\"\n\n[workspace]\nmembers = [\"helper\"]\n")
+                .unwrap(),
+            "0.3.0"
+        );
+        assert_eq!(
+            version("[package]\nname = \"a\"\nversion.workspace = true\n\n[workspace.package]\nversion = \"0.4.0\"\n")
+                .unwrap(),
+            "0.4.0"
+        );
+
+        let err = version("[workspace]\nmembers = [\"a\"]\n").unwrap_err();
+        assert!(err.to_string().contains("virtual workspace"), "{err}");
+        let err = version("[package]\nname = \"a\"\nversion.workspace = true\n\n[workspace]\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("also a workspace root"), "{err}");
+        let err = version("[package]\nname = \"a\"\n").unwrap_err();
+        assert!(
+            err.to_string().contains("single package without a version"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn current_version_of_a_virtual_workspace_from_its_members() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"a\", \"b\", \"tool\"]\n",
+        )
+        .unwrap();
+        let write_crate = |name: &str, manifest: &str| {
+            std::fs::create_dir_all(dir.path().join(name).join("src")).unwrap();
+            std::fs::write(
+                dir.path().join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\n{manifest}\n"),
+            )
+            .unwrap();
+            std::fs::write(dir.path().join(name).join("src/lib.rs"), "").unwrap();
+        };
+        write_crate("a", "version = \"0.2.0\"");
+        write_crate("b", "version = \"0.2.0\"");
+        // Crates that are not published do not need to be at the release version.
+        write_crate("tool", "version = \"0.0.1\"\npublish = false");
+
+        assert_eq!(get_current_version(dir.path()).unwrap(), "0.2.0");
+
+        write_crate("b", "version = \"0.3.0\"");
+        let err = get_current_version(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("a at 0.2.0; b at 0.3.0"), "{err}");
     }
 }